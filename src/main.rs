@@ -1,20 +1,31 @@
 mod utils;
 
-use std::sync::Arc;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 
 use axum::{
     Extension, Json, Router,
     extract::Path,
     http::StatusCode,
+    middleware,
     response::IntoResponse,
     routing::{get, post},
 };
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use utils::{
-    handler::{get_component, list_components, setup_config},
+    auth::{AUTH_CONFIG_PATH, SharedAuth, load_auth_config, require_auth},
+    cli::run_check,
+    handler::{
+        compare_refs, get_allowed_hosts, get_audit_log, get_changelog, get_component,
+        get_component_bundle, get_component_history, get_component_usages, get_dead_code_report,
+        get_sync_progress, list_components, search_components, set_allowed_hosts, setup_config,
+        sync_repository,
+    },
+    index_store::{load_current_index, restore_index},
+    limits::{LIMITS_PATH, load_limits, set_limits},
+    rate_limit::{RATE_LIMITS_PATH, RateLimiter, load_rate_limits, rate_limit},
     structs::{AppState, ComponentsList, SharedState},
-    utils::{check_dir_exist_or_create, execute_commande},
+    utils::check_dir_exist_or_create,
 };
 
 // Error handling
@@ -38,31 +49,108 @@ pub struct Component {}
 // Server setup
 #[tokio::main]
 async fn main() {
+    // `check` runs the dead code report instead of the server
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("check") {
+        std::process::exit(run_check(&args[2..]));
+    }
+
     // // Initialize logging
     // tracing_subscriber::fmt()
     //     .with_max_level(tracing::Level::DEBUG)
     //     .init();
     // Create router
 
+    // bounds on the processing of the repositories
+    match load_limits(LIMITS_PATH) {
+        Ok(limits) => set_limits(limits),
+        Err(err) => {
+            println!("{err}");
+            std::process::exit(1);
+        }
+    }
+
     // init app that that will store out branch current hash and the component state
 
     let mut app_state = AppState {
         comp_liste: Vec::new(),
         curr_ver: String::new(),
+        search_index: Arc::default(),
+        diagnostics: Vec::new(),
+        props: HashMap::new(),
+        examples: HashMap::new(),
     };
+    // the index saved by the last sync is served until the branch moves
+    if let Some(index) = load_current_index() {
+        println!(
+            "serving the stored index of {}@{} ({} components)",
+            index.repo,
+            index.commit,
+            index.components.len()
+        );
+        restore_index(&mut app_state, index);
+    }
     let state = SharedState::new(app_state.try_into().unwrap());
 
     check_dir_exist_or_create("/etc/compo-doc/config/rand.file");
     check_dir_exist_or_create("/etc/compo-doc/tmp/rand.file");
 
+    // api keys and JWT secret, without the file the API is read only
+    let auth: SharedAuth = match load_auth_config(AUTH_CONFIG_PATH) {
+        Ok(Some(config)) => {
+            if config.auth_disabled {
+                println!("authentication is disabled by {AUTH_CONFIG_PATH}, every caller is admin");
+            }
+            Arc::new(Some(config))
+        }
+        Ok(None) => {
+            println!("no {AUTH_CONFIG_PATH}, the API is read only");
+            Arc::new(None)
+        }
+        Err(err) => {
+            println!("{err}");
+            std::process::exit(1);
+        }
+    };
+
+    // per client rate limits and global caps on the merges and syncs
+    let limiter = match load_rate_limits(RATE_LIMITS_PATH) {
+        Ok(config) => Arc::new(RateLimiter::new(config)),
+        Err(err) => {
+            println!("{err}");
+            std::process::exit(1);
+        }
+    };
+
     let app = Router::new()
         .route("/config", post(setup_config))
+        .route(
+            "/admin/allowed-hosts",
+            get(get_allowed_hosts).put(set_allowed_hosts),
+        )
+        .route("/admin/audit", get(get_audit_log))
+        .route("/sync", post(sync_repository))
+        .route("/sync/progress", get(get_sync_progress))
         .route("/components", get(list_components))
         .route("/components/{id}", get(get_component))
+        .route("/components/{id}/bundle", get(get_component_bundle))
+        .route("/components/{id}/usages", get(get_component_usages))
+        .route("/components/{id}/history", get(get_component_history))
+        .route("/search", get(search_components))
+        .route("/compare", get(compare_refs))
+        .route("/changelog", get(get_changelog))
+        .route("/reports/dead-code", get(get_dead_code_report))
+        // the outer layer runs first, the limits apply before the authentication
+        .layer(middleware::from_fn_with_state(auth, require_auth))
+        .layer(middleware::from_fn_with_state(limiter, rate_limit))
         .layer(Extension(state))
         .with_state(AppState {
             comp_liste: Vec::new(),
             curr_ver: String::new(),
+            search_index: Arc::default(),
+            diagnostics: Vec::new(),
+            props: HashMap::new(),
+            examples: HashMap::new(),
         });
     // .with_state(app_state);
 
@@ -71,5 +159,11 @@ async fn main() {
 
     // tracing::info!("Server running on {}", listener.local_addr().unwrap());
     println!("server listenig on port 3000");
-    axum::serve(listener, app).await.unwrap();
+    // the peer address identifies the anonymous clients for the rate limits
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use swc_common::{
+    FileName, SourceMap,
+    comments::{CommentKind, SingleThreadedComments},
+    sync::Lrc,
+};
+use swc_ecma_ast::EsVersion;
+use swc_ecma_parser::{Parser, StringInput, Syntax, TsSyntax, lexer::Lexer};
+
+use crate::utils::{code_merge::principal_component_name, structs::LegacyInfo};
+
+/// A real comment of the source (never a comment-looking string)
+#[derive(Debug, Clone)]
+pub struct SourceComment {
+    pub is_block: bool,
+    pub text: String,
+    // byte offsets in the source
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Every comment of a tsx file, in source order.
+/// Falls back to a line scan when the file does not parse.
+pub fn source_comments(code: &str) -> Vec<SourceComment> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Anon.into(), code.to_string());
+    let comments = SingleThreadedComments::default();
+
+    let lexer = Lexer::new(
+        Syntax::Typescript(TsSyntax {
+            tsx: true,
+            ..Default::default()
+        }),
+        EsVersion::Es2022,
+        StringInput::from(&*fm),
+        Some(&comments),
+    );
+
+    if Parser::new_from(lexer).parse_module().is_err() {
+        return line_comments(code);
+    }
+
+    let offset = fm.start_pos.0 as usize;
+    let (leading, trailing) = comments.take_all();
+    let mut list: Vec<SourceComment> = leading
+        .borrow()
+        .values()
+        .chain(trailing.borrow().values())
+        .flatten()
+        .map(|c| SourceComment {
+            is_block: c.kind == CommentKind::Block,
+            text: c.text.to_string(),
+            start: c.span.lo.0 as usize - offset,
+            end: c.span.hi.0 as usize - offset,
+        })
+        .collect();
+
+    list.sort_by_key(|c| c.start);
+    list.dedup_by_key(|c| c.start);
+    list
+}
+
+fn line_comments(code: &str) -> Vec<SourceComment> {
+    let mut list = Vec::new();
+    let mut offset = 0;
+
+    for line in code.split_inclusive('\n') {
+        let indent = line.len() - line.trim_start().len();
+        if let Some(text) = line.trim().strip_prefix("//") {
+            list.push(SourceComment {
+                is_block: false,
+                text: text.to_string(),
+                start: offset + indent,
+                end: offset + line.trim_end().len(),
+            });
+        }
+        offset += line.len();
+    }
+
+    list
+}
+
+/// `<legacy reason="..." replacement="NewButton">` -> {reason, replacement}
+pub fn parse_marker_attributes(text: &str) -> HashMap<String, String> {
+    let attr_re = Regex::new(r#"([A-Za-z_][A-Za-z0-9_-]*)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+
+    attr_re
+        .captures_iter(text)
+        .map(|cap| {
+            let value = cap.get(2).or(cap.get(3)).map(|m| m.as_str()).unwrap_or("");
+            (cap[1].to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// The `//<name ...>` marker comment of a file, if any
+pub fn find_marker<'a>(comments: &'a [SourceComment], name: &str) -> Option<&'a SourceComment> {
+    let marker_re = Regex::new(&format!(r#"^\s*<{}(\s|>|$)"#, regex::escape(name))).unwrap();
+    comments
+        .iter()
+        .find(|c| !c.is_block && marker_re.is_match(&c.text))
+}
+
+/// The JSDoc block written right above the principal component
+pub fn component_doc_comment<'a>(
+    code: &str,
+    comments: &'a [SourceComment],
+) -> Option<&'a SourceComment> {
+    let name = principal_component_name(code)?;
+    let decl_re = Regex::new(&format!(
+        r#"^\s*(?:export\s+)?(?:default\s+)?(?:async\s+)?(?:function|const|let|class)\s+{}\b"#,
+        regex::escape(&name)
+    ))
+    .unwrap();
+
+    comments
+        .iter()
+        .filter(|c| c.is_block && c.text.starts_with('*'))
+        .find(|c| decl_re.is_match(&code[c.end..]))
+}
+
+/// Value of a `@tag value` line in a JSDoc block
+pub fn jsdoc_tag(doc: &str, tag: &str) -> Option<String> {
+    let tag_re = Regex::new(&format!(
+        r#"(?m)^[\s*]*@{}\b[ \t]*(.*)$"#,
+        regex::escape(tag)
+    ))
+    .unwrap();
+    tag_re
+        .captures(doc)
+        .map(|cap| cap[1].trim().trim_end_matches("*/").trim().to_string())
+}
+
+/// Read the legacy state of a component from its `//<legacy ...>` marker or
+/// the `@deprecated` tag of its JSDoc
+pub fn legacy_from_comments(code: &str, comments: &[SourceComment]) -> Option<LegacyInfo> {
+    if let Some(marker) = find_marker(comments, "legacy") {
+        let attrs = parse_marker_attributes(&marker.text);
+        let get = |keys: &[&str]| keys.iter().find_map(|k| attrs.get(*k).cloned());
+
+        return Some(LegacyInfo {
+            reason: get(&["reason"]),
+            replacement: get(&["replacement"]),
+            since: get(&["since"]),
+            remove_in: get(&["removeIn", "remove_in"]),
+        });
+    }
+
+    let doc = component_doc_comment(code, comments)?;
+    let reason = jsdoc_tag(&doc.text, "deprecated")?;
+
+    // "use {@link NewButton} instead"
+    let link_re = Regex::new(r#"\{@link\s+([^}\s|]+)"#).unwrap();
+    let replacement = link_re.captures(&reason).map(|cap| cap[1].to_string());
+
+    Some(LegacyInfo {
+        reason: if reason.is_empty() {
+            None
+        } else {
+            Some(reason)
+        },
+        replacement,
+        since: None,
+        remove_in: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy(code: &str) -> Option<LegacyInfo> {
+        legacy_from_comments(code, &source_comments(code))
+    }
+
+    #[test]
+    fn reads_the_legacy_marker() {
+        let code = r#"//<legacy reason="use the new one" replacement='NewButton' removeIn="3.0">
+export const Button = () => <button />;
+"#;
+        let info = legacy(code).unwrap();
+        assert_eq!(info.reason.as_deref(), Some("use the new one"));
+        assert_eq!(info.replacement.as_deref(), Some("NewButton"));
+        assert_eq!(info.remove_in.as_deref(), Some("3.0"));
+        assert_eq!(info.since, None);
+    }
+
+    #[test]
+    fn reads_the_deprecated_tag_of_the_component_doc() {
+        let code = r#"/** A helper */
+export const helper = () => 1;
+
+/**
+ * A button.
+ * @deprecated use {@link NewButton} instead
+ */
+export const Button = () => <button />;
+"#;
+        let info = legacy(code).unwrap();
+        assert_eq!(
+            info.reason.as_deref(),
+            Some("use {@link NewButton} instead")
+        );
+        assert_eq!(info.replacement.as_deref(), Some("NewButton"));
+
+        let code = "/** @deprecated */\nexport const Button = () => <button />;\n";
+        let info = legacy(code).unwrap();
+        assert_eq!(info.reason, None);
+    }
+
+    #[test]
+    fn ignores_markers_written_in_strings() {
+        let code = r#"const text = "//<legacy reason='no'>";
+/** `@deprecated` is only documented here */
+export const Button = () => <button>{text}</button>;
+"#;
+        assert!(legacy(code).is_none());
+    }
+
+    #[test]
+    fn scans_the_lines_of_a_file_that_does_not_parse() {
+        let code = "  //<legacy reason=\"old\">\nexport const Button = () => <button\n";
+        let comments = source_comments(code);
+        assert_eq!(comments.len(), 1);
+        assert_eq!(
+            &code[comments[0].start..comments[0].end],
+            "//<legacy reason=\"old\">"
+        );
+        assert!(find_marker(&comments, "legacy").is_some());
+        assert!(find_marker(&comments, "leg").is_none());
+    }
+
+    #[test]
+    fn reads_the_jsdoc_tags() {
+        let doc = "*\n * @category Forms\n * @owner  team-ui  \n * @keywords */";
+        assert_eq!(jsdoc_tag(doc, "category").as_deref(), Some("Forms"));
+        assert_eq!(jsdoc_tag(doc, "owner").as_deref(), Some("team-ui"));
+        assert_eq!(jsdoc_tag(doc, "keywords").as_deref(), Some(""));
+        assert_eq!(jsdoc_tag(doc, "cat"), None);
+    }
+}
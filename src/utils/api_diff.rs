@@ -0,0 +1,448 @@
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+
+use crate::utils::{
+    code_merge::principal_component_name,
+    search::extract_props,
+    structs::{ApiChange, ApiDiffReport, ComponentApiDiff, PropInfo, SemverLevel},
+    utils::git_output,
+};
+
+// share of props two components must have in common to be seen as a rename
+const RENAME_SIMILARITY: f64 = 0.8;
+
+/// A ref as accepted by git (branch, tag, sha, `HEAD~2`), anything else is
+/// refused before reaching git
+pub fn is_valid_ref(git_ref: &str) -> bool {
+    let ref_re = Regex::new(r"^[A-Za-z0-9][A-Za-z0-9._/\-]*(?:[~^][0-9]*)*$").unwrap();
+
+    git_ref.len() <= 200
+        && ref_re.is_match(git_ref)
+        && !git_ref.contains("..")
+        && !git_ref.contains("//")
+        && !git_ref.contains("@{")
+        && !git_ref.ends_with(".lock")
+        && !git_ref.ends_with('/')
+}
+
+/// Commit sha a ref points to in the repository
+pub fn resolve_ref(repo_dir: &str, git_ref: &str) -> Result<String, String> {
+    if !is_valid_ref(git_ref) {
+        return Err(format!("invalid ref {git_ref:?}"));
+    }
+
+    git_output(
+        repo_dir,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{git_ref}^{{commit}}"),
+        ],
+    )
+    .map(|sha| sha.trim().to_string())
+    .map_err(|_| format!("unknown ref {git_ref:?}"))
+}
+
+/// Resolved component of one file at a commit
+struct ComponentApi {
+    file: String,
+    name: String,
+    code: String,
+    props: Vec<PropInfo>,
+}
+
+/// Components of `component_dir` as they were at `commit`
+fn components_at(repo_dir: &str, commit: &str, component_dir: &str) -> Vec<ComponentApi> {
+    let dir = format!("{}/", component_dir.trim_matches('/'));
+    let Ok(listing) = git_output(repo_dir, &["ls-tree", "--name-only", commit, &dir]) else {
+        return Vec::new();
+    };
+
+    listing
+        .lines()
+        .filter(|f| f.ends_with(".tsx") || f.ends_with(".jsx"))
+        .filter(|f| !f.contains(".stories.") && !f.contains(".test.") && !f.contains(".spec."))
+        .filter_map(|file| {
+            let code = git_output(repo_dir, &["show", &format!("{commit}:{file}")]).ok()?;
+            let stem = file.rsplit('/').next()?.split('.').next()?.to_string();
+            Some(ComponentApi {
+                file: file.to_string(),
+                name: principal_component_name(&code).unwrap_or(stem),
+                props: extract_props(&code),
+                code,
+            })
+        })
+        .collect()
+}
+
+/// Compare the props API of every component between two refs
+pub fn diff_refs(
+    repo_dir: &str,
+    from: &str,
+    to: &str,
+    component_dir: &str,
+) -> Result<ApiDiffReport, String> {
+    let from_commit = resolve_ref(repo_dir, from)?;
+    let to_commit = resolve_ref(repo_dir, to)?;
+
+    let old = components_at(repo_dir, &from_commit, component_dir);
+    let new = components_at(repo_dir, &to_commit, component_dir);
+
+    let old_files: HashSet<&str> = old.iter().map(|c| c.file.as_str()).collect();
+    let new_files: HashSet<&str> = new.iter().map(|c| c.file.as_str()).collect();
+
+    let mut components = Vec::new();
+
+    for before in old.iter().filter(|c| new_files.contains(c.file.as_str())) {
+        let Some(after) = new.iter().find(|c| c.file == before.file) else {
+            continue;
+        };
+
+        let mut changes = diff_props(&before.props, &after.props);
+        if before.name != after.name {
+            changes.push(ApiChange {
+                kind: "component_renamed".to_string(),
+                prop: None,
+                detail: format!("{} was renamed {}", before.name, after.name),
+                level: SemverLevel::Major,
+            });
+        }
+        if changes.is_empty() && before.code != after.code {
+            changes.push(ApiChange {
+                kind: "implementation_changed".to_string(),
+                prop: None,
+                detail: "the source changed without touching the props".to_string(),
+                level: SemverLevel::Patch,
+            });
+        }
+
+        if !changes.is_empty() {
+            components.push(component_diff(&after.name, &after.file, changes));
+        }
+    }
+
+    let mut added: Vec<&ComponentApi> = new
+        .iter()
+        .filter(|c| !old_files.contains(c.file.as_str()))
+        .collect();
+
+    for before in old.iter().filter(|c| !new_files.contains(c.file.as_str())) {
+        // a moved file keeping its props is a rename, not a removal
+        let renamed_to = added
+            .iter()
+            .position(|after| props_similarity(&before.props, &after.props) >= RENAME_SIMILARITY);
+
+        let change = match renamed_to {
+            Some(index) => {
+                let after = added.remove(index);
+                ApiChange {
+                    kind: "component_renamed".to_string(),
+                    prop: None,
+                    detail: format!(
+                        "{} ({}) was renamed {} ({})",
+                        before.name, before.file, after.name, after.file
+                    ),
+                    level: SemverLevel::Major,
+                }
+            }
+            None => ApiChange {
+                kind: "component_removed".to_string(),
+                prop: None,
+                detail: format!("{} was removed", before.name),
+                level: SemverLevel::Major,
+            },
+        };
+        components.push(component_diff(&before.name, &before.file, vec![change]));
+    }
+
+    for after in added {
+        let change = ApiChange {
+            kind: "component_added".to_string(),
+            prop: None,
+            detail: format!("{} was added", after.name),
+            level: SemverLevel::Minor,
+        };
+        components.push(component_diff(&after.name, &after.file, vec![change]));
+    }
+
+    components.sort_by(|a, b| b.level.cmp(&a.level).then_with(|| a.file.cmp(&b.file)));
+
+    Ok(ApiDiffReport {
+        from: from.to_string(),
+        to: to.to_string(),
+        from_commit,
+        to_commit,
+        level: components
+            .iter()
+            .map(|c| c.level)
+            .max()
+            .unwrap_or(SemverLevel::None),
+        components,
+    })
+}
+
+fn component_diff(name: &str, file: &str, changes: Vec<ApiChange>) -> ComponentApiDiff {
+    ComponentApiDiff {
+        component: name.to_string(),
+        file: file.to_string(),
+        level: changes
+            .iter()
+            .map(|c| c.level)
+            .max()
+            .unwrap_or(SemverLevel::None),
+        changes,
+    }
+}
+
+/// Changes between the props of two versions of a component
+pub fn diff_props(before: &[PropInfo], after: &[PropInfo]) -> Vec<ApiChange> {
+    let mut changes = Vec::new();
+    let after_by_name: HashMap<&str, &PropInfo> =
+        after.iter().map(|p| (p.name.as_str(), p)).collect();
+    let before_names: HashSet<&str> = before.iter().map(|p| p.name.as_str()).collect();
+
+    let change = |kind: &str, prop: &str, detail: String, level: SemverLevel| ApiChange {
+        kind: kind.to_string(),
+        prop: Some(prop.to_string()),
+        detail,
+        level,
+    };
+
+    for old in before {
+        let Some(new) = after_by_name.get(old.name.as_str()) else {
+            changes.push(change(
+                "prop_removed",
+                &old.name,
+                format!("{} was removed", old.name),
+                SemverLevel::Major,
+            ));
+            continue;
+        };
+
+        match (old.optional, new.optional) {
+            (true, false) => changes.push(change(
+                "prop_required",
+                &old.name,
+                format!("{} became required", old.name),
+                SemverLevel::Major,
+            )),
+            (false, true) => changes.push(change(
+                "prop_optional",
+                &old.name,
+                format!("{} became optional", old.name),
+                SemverLevel::Minor,
+            )),
+            _ => {}
+        }
+
+        if let Some(type_change) = diff_type(&old.name, &old.prop_type, &new.prop_type) {
+            changes.push(type_change);
+        }
+
+        if old.description != new.description {
+            changes.push(change(
+                "prop_doc_changed",
+                &old.name,
+                format!("the description of {} changed", old.name),
+                SemverLevel::Patch,
+            ));
+        }
+    }
+
+    for new in after
+        .iter()
+        .filter(|p| !before_names.contains(p.name.as_str()))
+    {
+        let (kind, level) = if new.optional {
+            ("prop_added", SemverLevel::Minor)
+        } else {
+            ("required_prop_added", SemverLevel::Major)
+        };
+        changes.push(change(
+            kind,
+            &new.name,
+            format!(
+                "{}{}: {} was added",
+                new.name,
+                if new.optional { "?" } else { "" },
+                new.prop_type
+            ),
+            level,
+        ));
+    }
+
+    changes
+}
+
+/// Classify the change of a prop type, union members are compared one by one
+fn diff_type(prop: &str, before: &str, after: &str) -> Option<ApiChange> {
+    let before_members = union_members(before);
+    let after_members = union_members(after);
+    if before_members == after_members {
+        return None;
+    }
+
+    let removed: Vec<&str> = before_members
+        .iter()
+        .filter(|m| !after_members.contains(*m))
+        .map(String::as_str)
+        .collect();
+    let added: Vec<&str> = after_members
+        .iter()
+        .filter(|m| !before_members.contains(*m))
+        .map(String::as_str)
+        .collect();
+
+    let is_wide = |t: &str| matches!(t, "any" | "unknown");
+
+    let (kind, detail, level) = if is_wide(before) && !is_wide(after) {
+        (
+            "type_narrowed",
+            format!("{prop} went from {before} to {after}"),
+            SemverLevel::Major,
+        )
+    } else if is_wide(after) {
+        (
+            "type_widened",
+            format!("{prop} went from {before} to {after}"),
+            SemverLevel::Minor,
+        )
+    } else if removed.iter().all(|m| is_literal(m)) && !removed.is_empty() {
+        (
+            "union_member_removed",
+            format!("{prop} no longer accepts {}", removed.join(" | ")),
+            SemverLevel::Major,
+        )
+    } else if removed.is_empty() {
+        (
+            "type_widened",
+            format!("{prop} now also accepts {}", added.join(" | ")),
+            SemverLevel::Minor,
+        )
+    } else {
+        (
+            "type_changed",
+            format!("{prop} went from {before} to {after}"),
+            SemverLevel::Major,
+        )
+    };
+
+    Some(ApiChange {
+        kind: kind.to_string(),
+        prop: Some(prop.to_string()),
+        detail,
+        level,
+    })
+}
+
+/// Members of a top level union (`"sm" | "md"`), a single member otherwise
+fn union_members(prop_type: &str) -> Vec<String> {
+    let mut members = Vec::new();
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut current = String::new();
+
+    for c in prop_type.chars() {
+        // the `>` of an arrow (`() => void`) closes nothing
+        let arrow = c == '>' && current.ends_with('=');
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'' | '`') => quote = Some(c),
+            (None, '(' | '<' | '{' | '[') => depth += 1,
+            (None, '>') if arrow => {}
+            (None, ')' | '>' | '}' | ']') => depth -= 1,
+            (None, '|') if depth == 0 => {
+                members.push(std::mem::take(&mut current).trim().to_string());
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    members.push(current.trim().to_string());
+
+    let mut members: Vec<String> = members.into_iter().filter(|m| !m.is_empty()).collect();
+    members.sort();
+    members.dedup();
+    members
+}
+
+fn is_literal(member: &str) -> bool {
+    member.starts_with(['"', '\'', '`'])
+        || member.parse::<f64>().is_ok()
+        || matches!(member, "true" | "false" | "null" | "undefined")
+}
+
+/// Share of prop names two components have in common
+fn props_similarity(a: &[PropInfo], b: &[PropInfo]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 0.0;
+    }
+    let a: HashSet<&str> = a.iter().map(|p| p.name.as_str()).collect();
+    let b: HashSet<&str> = b.iter().map(|p| p.name.as_str()).collect();
+
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_the_top_level_union_only() {
+        assert_eq!(union_members(r#""md" | "sm""#), [r#""md""#, r#""sm""#]);
+        assert_eq!(
+            union_members(r#"Array<"a" | "b"> | { kind: "x" | "y" }"#),
+            [r#"Array<"a" | "b">"#, r#"{ kind: "x" | "y" }"#]
+        );
+        assert_eq!(union_members(r#""a | b" | 'c'"#), [r#""a | b""#, "'c'"]);
+        assert_eq!(union_members("string"), ["string"]);
+    }
+
+    #[test]
+    fn keeps_the_arrows_inside_their_member() {
+        assert_eq!(
+            union_members("(() => void) | null"),
+            ["(() => void)", "null"]
+        );
+        // the arrow used to close the generic and split the union inside it
+        assert_eq!(
+            union_members("Array<(a: string) => void | string> | number"),
+            ["Array<(a: string) => void | string>", "number"]
+        );
+    }
+
+    #[test]
+    fn classifies_the_type_changes() {
+        let change = |before, after| diff_type("size", before, after).map(|c| (c.kind, c.level));
+
+        assert_eq!(change(r#""sm" | "md""#, r#""md" | "sm""#), None);
+        assert_eq!(
+            change(r#""sm" | "md""#, r#""sm""#),
+            Some(("union_member_removed".to_string(), SemverLevel::Major))
+        );
+        assert_eq!(
+            change(r#""sm""#, r#""sm" | "md""#),
+            Some(("type_widened".to_string(), SemverLevel::Minor))
+        );
+        assert_eq!(
+            change("any", "string"),
+            Some(("type_narrowed".to_string(), SemverLevel::Major))
+        );
+        assert_eq!(
+            change("string", "unknown"),
+            Some(("type_widened".to_string(), SemverLevel::Minor))
+        );
+        assert_eq!(
+            change("string", "number"),
+            Some(("type_changed".to_string(), SemverLevel::Major))
+        );
+        assert_eq!(
+            change("Array<() => void> | null", "Array<() => void>"),
+            Some(("union_member_removed".to_string(), SemverLevel::Major))
+        );
+    }
+}
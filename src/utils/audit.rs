@@ -0,0 +1,204 @@
+use std::{
+    fs::{OpenOptions, metadata, rename},
+    io::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::utils::{
+    structs::{AuditEvent, AuditQuery},
+    utils::{check_dir_exist_or_create, read_from_file_ut},
+};
+
+pub const AUDIT_LOG_PATH: &str = "/etc/compo-doc/audit/audit.log";
+
+// the log is rotated past this size, audit.log.1 being the most recent archive
+const MAX_AUDIT_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_ROTATED_FILES: usize = 5;
+
+// one writer at a time so the lines and the rotation never interleave
+static AUDIT_LOCK: Mutex<()> = Mutex::new(());
+
+pub fn now_unix() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Append an event to the audit log, a failure to write is only printed so
+/// it never fails the audited request
+pub fn record(event: AuditEvent) {
+    let line = match serde_json::to_string(&event) {
+        Ok(line) => line,
+        Err(err) => {
+            println!("could not serialize audit event : {err}");
+            return;
+        }
+    };
+
+    let _guard = AUDIT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    check_dir_exist_or_create(AUDIT_LOG_PATH);
+    append_line(AUDIT_LOG_PATH, &line, MAX_AUDIT_FILE_SIZE);
+}
+
+fn append_line(path: &str, line: &str, max_size: u64) {
+    rotate_if_needed(path, max_size);
+
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{line}"));
+    if let Err(err) = written {
+        println!("could not write the audit log : {err}");
+    }
+}
+
+/// Shift audit.log.N to audit.log.N+1 and start a new file, the oldest archive
+/// is overwritten
+fn rotate_if_needed(path: &str, max_size: u64) {
+    let too_big = metadata(path).is_ok_and(|m| m.len() >= max_size);
+    if !too_big {
+        return;
+    }
+
+    for index in (1..MAX_ROTATED_FILES).rev() {
+        let _ = rename(format!("{path}.{index}"), format!("{path}.{}", index + 1));
+    }
+    if let Err(err) = rename(path, format!("{path}.1")) {
+        println!("could not rotate the audit log : {err}");
+    }
+}
+
+/// Events matching the query, newest first, with the total before pagination
+pub fn read_events(query: &AuditQuery, page: usize, per_page: usize) -> (usize, Vec<AuditEvent>) {
+    read_events_from(AUDIT_LOG_PATH, query, page, per_page)
+}
+
+fn read_events_from(
+    path: &str,
+    query: &AuditQuery,
+    page: usize,
+    per_page: usize,
+) -> (usize, Vec<AuditEvent>) {
+    // oldest archive first so the events stay in order
+    let mut files: Vec<String> = (1..=MAX_ROTATED_FILES)
+        .rev()
+        .map(|index| format!("{path}.{index}"))
+        .collect();
+    files.push(path.to_string());
+
+    let mut events: Vec<AuditEvent> = files
+        .iter()
+        .filter_map(|file| read_from_file_ut(file).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<AuditEvent>(line).ok())
+                .collect::<Vec<_>>()
+        })
+        .filter(|e| query.since.is_none_or(|since| e.timestamp >= since))
+        .filter(|e| query.until.is_none_or(|until| e.timestamp <= until))
+        .filter(|e| query.event.as_ref().is_none_or(|event| &e.event == event))
+        .filter(|e| query.actor.as_ref().is_none_or(|actor| &e.actor == actor))
+        .collect();
+
+    events.reverse();
+    let total = events.len();
+
+    (
+        total,
+        events
+            .into_iter()
+            .skip(page.saturating_sub(1).saturating_mul(per_page))
+            .take(per_page)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn event(timestamp: i64, event: &str, actor: &str) -> String {
+        let mut event = AuditEvent::new(event, actor, true);
+        event.timestamp = timestamp;
+        serde_json::to_string(&event).unwrap()
+    }
+
+    fn log_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("compo-doc-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("audit.log").to_string_lossy().into_owned()
+    }
+
+    fn timestamps(events: Vec<AuditEvent>) -> Vec<i64> {
+        events.into_iter().map(|e| e.timestamp).collect()
+    }
+
+    #[test]
+    fn rotates_the_log_and_keeps_the_last_archives() {
+        let path = log_path("audit-rotate");
+        // every line is bigger than the limit, each write rotates the previous
+        for timestamp in 1..=8 {
+            append_line(&path, &event(timestamp, "sync", "ci"), 10);
+        }
+
+        let archives: Vec<String> = (1..=MAX_ROTATED_FILES + 1)
+            .filter(|i| Path::new(&format!("{path}.{i}")).exists())
+            .map(|i| format!("{path}.{i}"))
+            .collect();
+        assert_eq!(archives.len(), MAX_ROTATED_FILES);
+
+        // the current file and the archives are read back in order
+        let (total, events) = read_events_from(&path, &AuditQuery::default(), 1, 100);
+        assert_eq!(total, 1 + MAX_ROTATED_FILES);
+        assert_eq!(timestamps(events), [8, 7, 6, 5, 4, 3]);
+
+        std::fs::remove_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn filters_and_pages_the_events() {
+        let path = log_path("audit-read");
+        let lines = [
+            event(10, "sync", "ci"),
+            event(20, "config_changed", "ana"),
+            "not json".to_string(),
+            event(30, "sync", "ana"),
+            event(40, "sync", "ci"),
+        ];
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let read = |query: AuditQuery, page| {
+            let (total, events) = read_events_from(&path, &query, page, 2);
+            (total, timestamps(events))
+        };
+
+        assert_eq!(read(AuditQuery::default(), 1), (4, vec![40, 30]));
+        assert_eq!(read(AuditQuery::default(), 2), (4, vec![20, 10]));
+        // page 0 is the first one, the pages past the end are empty
+        assert_eq!(read(AuditQuery::default(), 0), (4, vec![40, 30]));
+        assert_eq!(read(AuditQuery::default(), usize::MAX), (4, vec![]));
+
+        let query = AuditQuery {
+            event: Some("sync".to_string()),
+            actor: Some("ci".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(read(query, 1), (2, vec![40, 10]));
+        let query = AuditQuery {
+            since: Some(20),
+            until: Some(30),
+            ..Default::default()
+        };
+        assert_eq!(read(query, 1), (2, vec![30, 20]));
+
+        std::fs::remove_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,345 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, Method, Response, StatusCode, header},
+    middleware::Next,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{http_utils::json_response_builder, structs::JsonResponse};
+
+pub const AUTH_CONFIG_PATH: &str = "/etc/compo-doc/config/auth.json";
+
+/// What a caller is allowed to do, each role can do what the previous ones can
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // list, get and search the components
+    Reader,
+    // trigger syncs
+    Maintainer,
+    // change the configuration
+    Admin,
+}
+
+/// Content of `auth.json`
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuthConfig {
+    // every caller is admin, only for trusted networks
+    #[serde(default)]
+    pub auth_disabled: bool,
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    // HS256 key the bearer tokens are signed with
+    pub jwt_secret: Option<String>,
+    pub jwt_issuer: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiKey {
+    // who the key was given to, shown in the logs
+    pub name: String,
+    pub key: String,
+    pub role: Role,
+}
+
+/// Claims expected in the bearer tokens
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    role: Role,
+}
+
+/// The authenticated caller, added to the request extensions by `require_auth`
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub name: String,
+    pub role: Role,
+}
+
+/// `None` without `auth.json`, the API is then read only
+pub type SharedAuth = Arc<Option<AuthConfig>>;
+
+/// Read `auth.json`. Without it the callers are anonymous readers and an
+/// invalid one is an error, so a missing file or a typo never opens the API.
+pub fn load_auth_config(path: &str) -> Result<Option<AuthConfig>, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("could not read {path} : {err}")),
+    };
+
+    let config: AuthConfig =
+        serde_json::from_str(&content).map_err(|err| format!("invalid {path} : {err}"))?;
+
+    if config.api_keys.iter().any(|k| k.key.len() < 16) {
+        return Err(format!("{path} : api keys must be at least 16 characters"));
+    }
+    if config.jwt_secret.as_ref().is_some_and(|s| s.len() < 32) {
+        return Err(format!(
+            "{path} : jwt_secret must be at least 32 characters"
+        ));
+    }
+
+    Ok(Some(config))
+}
+
+/// Least role needed to call a route
+pub fn required_role(method: &Method, path: &str) -> Role {
+    match (method, path) {
+        (&Method::POST, "/config") => Role::Admin,
+        (_, path) if path.starts_with("/admin") => Role::Admin,
+        (&Method::POST, "/sync") => Role::Maintainer,
+        _ => Role::Reader,
+    }
+}
+
+/// Identify the caller from an `X-API-Key` header or an `Authorization: Bearer`
+/// header holding an api key or a JWT
+pub fn authenticate(config: &AuthConfig, headers: &HeaderMap) -> Option<Caller> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim);
+    let api_key = headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim);
+
+    for candidate in [api_key, bearer].into_iter().flatten() {
+        if let Some(key) = config
+            .api_keys
+            .iter()
+            .find(|k| constant_time_eq(k.key.as_bytes(), candidate.as_bytes()))
+        {
+            return Some(Caller {
+                name: key.name.clone(),
+                role: key.role,
+            });
+        }
+    }
+
+    let (Some(token), Some(secret)) = (bearer, &config.jwt_secret) else {
+        return None;
+    };
+
+    let mut validation = Validation::new(Algorithm::HS256);
+    if let Some(issuer) = &config.jwt_issuer {
+        validation.set_issuer(&[issuer]);
+    }
+
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    )
+    .ok()
+    .map(|data| Caller {
+        name: data.claims.sub,
+        role: data.claims.role,
+    })
+}
+
+/// Compare secrets without leaking where they differ through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The caller of a request, none when the credentials are missing or invalid.
+/// Without `auth.json` everybody is an anonymous reader, everybody is admin
+/// only when the file disables the authentication.
+pub fn identify(config: Option<&AuthConfig>, headers: &HeaderMap) -> Option<Caller> {
+    let anonymous = |role| Caller {
+        name: "anonymous".to_string(),
+        role,
+    };
+
+    match config {
+        None => Some(anonymous(Role::Reader)),
+        Some(config) if config.auth_disabled => Some(anonymous(Role::Admin)),
+        Some(config) => authenticate(config, headers),
+    }
+}
+
+/// Middleware checking the caller role against the route
+pub async fn require_auth(
+    State(auth): State<SharedAuth>,
+    mut request: Request,
+    next: Next,
+) -> Response<Body> {
+    let caller = match identify(auth.as_ref().as_ref(), request.headers()) {
+        Some(caller) => caller,
+        None => {
+            let mut response = json_response_builder(
+                StatusCode::UNAUTHORIZED,
+                JsonResponse::<String>::make_error("authentication required".to_string()),
+            );
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+            return response;
+        }
+    };
+
+    let required = required_role(request.method(), request.uri().path());
+    if caller.role < required {
+        println!(
+            "{} ({:?}) denied {} {}",
+            caller.name,
+            caller.role,
+            request.method(),
+            request.uri().path()
+        );
+        return json_response_builder(
+            StatusCode::FORBIDDEN,
+            JsonResponse::<String>::make_error(format!(
+                "this route needs the {} role",
+                format!("{required:?}").to_lowercase()
+            )),
+        );
+    }
+
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::json;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            api_keys: vec![ApiKey {
+                name: "ci".to_string(),
+                key: "key-of-the-ci-0001".to_string(),
+                role: Role::Maintainer,
+            }],
+            jwt_secret: Some(SECRET.to_string()),
+            jwt_issuer: Some("compo-doc".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value.parse().unwrap());
+        headers
+    }
+
+    fn token(claims: serde_json::Value, secret: &str) -> String {
+        encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn exp(offset: i64) -> i64 {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        now + offset
+    }
+
+    fn bearer(token: &str) -> Option<Caller> {
+        authenticate(
+            &config(),
+            &headers(header::AUTHORIZATION, &format!("Bearer {token}")),
+        )
+    }
+
+    #[test]
+    fn accepts_the_api_keys_in_both_headers() {
+        let caller = authenticate(
+            &config(),
+            &headers("x-api-key".parse().unwrap(), "key-of-the-ci-0001"),
+        )
+        .unwrap();
+        assert_eq!(
+            (caller.name.as_str(), caller.role),
+            ("ci", Role::Maintainer)
+        );
+
+        assert!(bearer("key-of-the-ci-0001").is_some());
+        assert!(bearer("key-of-the-ci-000").is_none());
+        assert!(bearer("key-of-the-ci-00011").is_none());
+    }
+
+    #[test]
+    fn reads_the_role_of_a_signed_token() {
+        let valid = json!({"sub": "ana", "role": "admin", "iss": "compo-doc", "exp": exp(600)});
+        let caller = bearer(&token(valid.clone(), SECRET)).unwrap();
+        assert_eq!((caller.name.as_str(), caller.role), ("ana", Role::Admin));
+
+        // forged, expired, foreign or unknown role tokens are all rejected
+        assert!(bearer(&token(valid, "another secret of 32 characters!!")).is_none());
+        let expired = json!({"sub": "ana", "role": "admin", "iss": "compo-doc", "exp": exp(-600)});
+        assert!(bearer(&token(expired, SECRET)).is_none());
+        let foreign = json!({"sub": "ana", "role": "admin", "iss": "other", "exp": exp(600)});
+        assert!(bearer(&token(foreign, SECRET)).is_none());
+        let root = json!({"sub": "ana", "role": "root", "iss": "compo-doc", "exp": exp(600)});
+        assert!(bearer(&token(root, SECRET)).is_none());
+    }
+
+    #[test]
+    fn rejects_unsigned_tokens() {
+        // {"alg":"none"} with admin claims and no signature
+        let unsigned = "eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.eyJzdWIiOiJhbmEiLCJyb2xlIjoiYWRtaW4iLCJpc3MiOiJjb21wby1kb2MiLCJleHAiOjQxMDI0NDQ4MDB9.";
+        assert!(bearer(unsigned).is_none());
+    }
+
+    #[test]
+    fn fails_closed_without_the_auth_config() {
+        let caller = identify(None, &HeaderMap::new()).unwrap();
+        assert_eq!(caller.role, Role::Reader);
+        assert!(caller.role < required_role(&Method::POST, "/config"));
+        assert!(caller.role < required_role(&Method::POST, "/sync"));
+
+        // only an explicit opt out opens the API
+        let disabled = AuthConfig {
+            auth_disabled: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            identify(Some(&disabled), &HeaderMap::new()).unwrap().role,
+            Role::Admin
+        );
+        assert!(identify(Some(&config()), &HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn orders_the_roles_of_the_routes() {
+        assert_eq!(required_role(&Method::POST, "/config"), Role::Admin);
+        assert_eq!(required_role(&Method::GET, "/admin/audit"), Role::Admin);
+        assert_eq!(required_role(&Method::POST, "/sync"), Role::Maintainer);
+        assert_eq!(required_role(&Method::GET, "/components"), Role::Reader);
+        assert!(Role::Reader < Role::Maintainer && Role::Maintainer < Role::Admin);
+    }
+
+    #[test]
+    fn refuses_weak_secrets() {
+        let path = std::env::temp_dir().join(format!("compo-doc-auth-{}", std::process::id()));
+        std::fs::write(&path, r#"{"jwt_secret": "short"}"#).unwrap();
+        assert!(load_auth_config(path.to_str().unwrap()).is_err());
+
+        std::fs::write(
+            &path,
+            r#"{"api_keys": [{"name": "ci", "key": "short", "role": "admin"}]}"#,
+        )
+        .unwrap();
+        assert!(load_auth_config(path.to_str().unwrap()).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(load_auth_config(path.to_str().unwrap()).unwrap().is_none());
+    }
+}
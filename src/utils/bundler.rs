@@ -0,0 +1,248 @@
+use anyhow::{Result, anyhow};
+use swc_common::{
+    FileName, GLOBALS, Globals, Mark, SourceMap,
+    comments::SingleThreadedComments,
+    errors::{HANDLER, Handler},
+    sync::Lrc,
+};
+use swc_ecma_ast::{
+    CallExpr, Callee, EsVersion, ExportAll, Expr, ImportDecl, Lit, NamedExport, Program, Str,
+};
+use swc_ecma_codegen::{Emitter, text_writer::JsWriter};
+use swc_ecma_parser::{Parser, StringInput, Syntax, TsSyntax, lexer::Lexer};
+use swc_ecma_transforms_base::{fixer::fixer, hygiene::hygiene, resolver};
+use swc_ecma_transforms_react::{Options as JsxOptions, Runtime, jsx};
+use swc_ecma_transforms_typescript::strip;
+use swc_ecma_visit::{VisitMut, VisitMutWith, visit_mut_pass};
+
+use crate::utils::code_merge::{merge_in_root, principal_component_name};
+
+/// CDN used to resolve bare npm imports when the caller does not provide one
+pub const DEFAULT_CDN_BASE: &str = "https://esm.sh/";
+
+/// CDNs serving npm packages as ES modules a caller may choose from
+pub const ALLOWED_CDN_BASES: &[&str] = &[
+    DEFAULT_CDN_BASE,
+    "https://esm.run/",
+    "https://cdn.skypack.dev/",
+];
+
+/// The allowed CDN a base points to, the trailing slash is optional
+pub fn allowed_cdn_base(cdn_base: &str) -> Option<&'static str> {
+    let cdn_base = cdn_base.trim_end_matches('/');
+    ALLOWED_CDN_BASES
+        .iter()
+        .copied()
+        .find(|allowed| allowed.trim_end_matches('/') == cdn_base)
+}
+
+/// Build a self-contained ES module for a component :
+/// merge the repo-local components, strip the typescript, transform the jsx
+/// and point every bare npm import to `cdn_base`.
+pub fn bundle_component(tsx: &str, repo: &str, cdn_base: &str) -> Result<String> {
+    bundle_in_root(tsx, &format!("/etc/compo-doc/tmp/{repo}"), cdn_base)
+}
+
+fn bundle_in_root(tsx: &str, repo_root: &str, cdn_base: &str) -> Result<String> {
+    let (mut merged, _) = merge_in_root(tsx, repo_root).map_err(|d| anyhow!(d.message()))?;
+
+    // the merge strips every export, give the module back its entry point
+    if let Some(name) = principal_component_name(tsx) {
+        merged.push_str(&format!("\nexport default {name};\n"));
+    }
+
+    transpile_tsx(&merged, cdn_base)
+}
+
+/// Turn tsx source into plain browser javascript (types removed, jsx compiled
+/// with the automatic runtime, bare imports pointed to `cdn_base`)
+pub fn transpile_tsx(code: &str, cdn_base: &str) -> Result<String> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Anon.into(), code.to_string());
+    let comments = SingleThreadedComments::default();
+
+    let lexer = Lexer::new(
+        Syntax::Typescript(TsSyntax {
+            tsx: true,
+            ..Default::default()
+        }),
+        EsVersion::Es2022,
+        StringInput::from(&*fm),
+        Some(&comments),
+    );
+
+    let module = Parser::new_from(lexer)
+        .parse_module()
+        .map_err(|e| anyhow!("could not parse component: {:?}", e.kind()))?;
+
+    let handler = Handler::with_emitter_writer(Box::new(std::io::stderr()), Some(cm.clone()));
+
+    GLOBALS.set(&Globals::new(), || {
+        HANDLER.set(&handler, || {
+            let unresolved_mark = Mark::new();
+            let top_level_mark = Mark::new();
+
+            let program = Program::Module(module)
+                .apply(resolver(unresolved_mark, top_level_mark, true))
+                .apply(strip(unresolved_mark, top_level_mark))
+                .apply(jsx(
+                    cm.clone(),
+                    Some(&comments),
+                    JsxOptions {
+                        runtime: Some(Runtime::Automatic),
+                        ..Default::default()
+                    },
+                    top_level_mark,
+                    unresolved_mark,
+                ))
+                // after the jsx pass, its runtime import is bare too
+                .apply(visit_mut_pass(BareImportRewriter::new(cdn_base)))
+                .apply(hygiene())
+                .apply(fixer(Some(&comments)));
+
+            let mut buf = Vec::new();
+            {
+                let writer = JsWriter::new(cm.clone(), "\n", &mut buf, None);
+                let mut emitter = Emitter {
+                    cfg: swc_ecma_codegen::Config::default(),
+                    cm: cm.clone(),
+                    comments: Some(&comments),
+                    wr: writer,
+                };
+                emitter.emit_program(&program)?;
+            }
+
+            Ok(String::from_utf8(buf)?)
+        })
+    })
+}
+
+/// Prefix every bare module specifier (`react`, `@scope/pkg/sub`) with the
+/// CDN / import-map base, relative and absolute specifiers are left alone.
+/// Only the specifiers of imports, re-exports and `import()` are touched,
+/// never a string or a comment looking like one.
+struct BareImportRewriter {
+    base: String,
+}
+
+impl BareImportRewriter {
+    fn new(cdn_base: &str) -> Self {
+        let base = if cdn_base.ends_with('/') {
+            cdn_base.to_string()
+        } else {
+            format!("{cdn_base}/")
+        };
+        BareImportRewriter { base }
+    }
+
+    fn rewrite(&self, spec: &mut Str) {
+        if is_bare_specifier(&spec.value) {
+            spec.value = format!("{}{}", self.base, spec.value).into();
+            // printed again from the value
+            spec.raw = None;
+        }
+    }
+}
+
+impl VisitMut for BareImportRewriter {
+    fn visit_mut_import_decl(&mut self, import: &mut ImportDecl) {
+        self.rewrite(&mut import.src);
+    }
+
+    fn visit_mut_named_export(&mut self, export: &mut NamedExport) {
+        if let Some(src) = &mut export.src {
+            self.rewrite(src);
+        }
+    }
+
+    fn visit_mut_export_all(&mut self, export: &mut ExportAll) {
+        self.rewrite(&mut export.src);
+    }
+
+    fn visit_mut_call_expr(&mut self, call: &mut CallExpr) {
+        call.visit_mut_children_with(self);
+
+        // a computed import() is left to the browser
+        if matches!(call.callee, Callee::Import(_))
+            && let Some(arg) = call.args.first_mut()
+            && let Expr::Lit(Lit::Str(spec)) = &mut *arg.expr
+        {
+            self.rewrite(spec);
+        }
+    }
+}
+
+pub fn is_bare_specifier(spec: &str) -> bool {
+    !(spec.starts_with('.') || spec.starts_with('/') || spec.contains("://"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_accepts_the_allowed_cdns() {
+        assert_eq!(allowed_cdn_base("https://esm.sh"), Some(DEFAULT_CDN_BASE));
+        assert_eq!(
+            allowed_cdn_base("https://cdn.skypack.dev/"),
+            Some("https://cdn.skypack.dev/")
+        );
+        assert_eq!(allowed_cdn_base("https://evil.example/"), None);
+        assert_eq!(allowed_cdn_base("https://esm.sh/x"), None);
+    }
+
+    #[test]
+    fn bundles_a_component_with_bare_relative_and_dynamic_imports() {
+        let dir = std::env::temp_dir().join(format!("compo-doc-bundle-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("components")).unwrap();
+        std::fs::write(
+            dir.join("components/Icon.tsx"),
+            "export default function Icon() { return <i />; }",
+        )
+        .unwrap();
+
+        let tsx = r#"import { useState } from "react";
+import { format } from "./format.js";
+import Icon from "./Icon";
+
+// import x from "not-a-module";
+const hint = 'loaded with import("lodash") and from "react"';
+
+export default function Card() {
+  const [open] = useState(false);
+  const load = () => import("lodash");
+  const local = () => import("./lazy.js");
+  return <div title={hint}><Icon />{format(open)}</div>;
+}
+"#;
+        let js = bundle_in_root(tsx, &dir.to_string_lossy(), "https://esm.sh").unwrap();
+
+        assert!(js.contains(r#"from "https://esm.sh/react""#));
+        assert!(js.contains(r#"from "https://esm.sh/react/jsx-runtime""#));
+        assert!(js.contains(r#"import("https://esm.sh/lodash")"#));
+        // the relative imports stay, the component one is merged
+        assert!(js.contains(r#"from "./format.js""#));
+        assert!(js.contains(r#"import("./lazy.js")"#));
+        assert!(!js.contains(r#""./Icon""#));
+        assert!(js.contains("function Icon"));
+        // strings and comments are never rewritten
+        assert!(js.contains(r#"import("lodash") and from "react""#));
+        assert!(!js.contains("esm.sh/not-a-module"));
+        assert!(js.contains("export default Card"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rewrites_the_re_exports() {
+        let js = transpile_tsx(
+            "export * from \"zod\";\nexport { a } from \"@scope/pkg/sub\";\nexport { b } from \"/abs.js\";",
+            "https://esm.sh/",
+        )
+        .unwrap();
+
+        assert!(js.contains(r#"export * from "https://esm.sh/zod""#));
+        assert!(js.contains(r#"from "https://esm.sh/@scope/pkg/sub""#));
+        assert!(js.contains(r#"from "/abs.js""#));
+    }
+}
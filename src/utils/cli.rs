@@ -0,0 +1,84 @@
+use crate::utils::{
+    dead_code::dead_code_report,
+    utils::{extract_repo_info, read_repo_config},
+};
+
+const CHECK_USAGE: &str = "usage : check [repo_dir] [--components <dir>]";
+
+/// `check` subcommand : print the dead code report of a repository, the exit
+/// code is 1 when something can be pruned so it can fail a CI job
+pub fn run_check(args: &[String]) -> i32 {
+    let mut repo_dir = None;
+    let mut component_dir = "components".to_string();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--components" => match args.next() {
+                Some(dir) => component_dir = dir.clone(),
+                None => {
+                    println!("{CHECK_USAGE}");
+                    return 2;
+                }
+            },
+            "-h" | "--help" => {
+                println!("{CHECK_USAGE}");
+                return 0;
+            }
+            _ if repo_dir.is_none() => repo_dir = Some(arg.clone()),
+            _ => {
+                println!("{CHECK_USAGE}");
+                return 2;
+            }
+        }
+    }
+
+    // defaults to the repository synced by the server
+    let repo_dir = match repo_dir.or_else(configured_repo_dir) {
+        Some(dir) => dir,
+        None => {
+            println!("no repository given and none configured");
+            println!("{CHECK_USAGE}");
+            return 2;
+        }
+    };
+
+    let report = dead_code_report(&repo_dir, &component_dir);
+
+    for component in &report.unused_components {
+        println!(
+            "unused component : {} ({}){}",
+            component.name,
+            component.file,
+            if component.documented_by.is_empty() {
+                String::new()
+            } else {
+                format!(" only in {}", component.documented_by.join(", "))
+            }
+        );
+    }
+    for prop in &report.dead_props {
+        println!(
+            "dead prop : {}.{} ({})",
+            prop.component, prop.prop, prop.file
+        );
+    }
+
+    if report.is_empty() {
+        println!("no dead code found");
+        0
+    } else {
+        println!(
+            "{} unused components, {} dead props",
+            report.unused_components.len(),
+            report.dead_props.len()
+        );
+        1
+    }
+}
+
+fn configured_repo_dir() -> Option<String> {
+    let config = read_repo_config().ok()?;
+    let (_username, repo, _branch) = extract_repo_info(&config)?;
+    Some(format!("/etc/compo-doc/tmp/{repo}"))
+}
@@ -2,7 +2,12 @@ use std::collections::{HashMap, HashSet};
 
 use regex::Regex;
 
-use crate::utils::utils::{convert_hash, read_from_file_ut};
+use crate::utils::{
+    import_merge::{rename_identifiers, take_imports, top_level_names},
+    limits::{check_file_size, check_merge_depth, check_merge_work, check_merged_size},
+    structs::LimitDiagnostic,
+    utils::{convert_hash, read_repo_file},
+};
 
 pub fn get_imported_components(tsx: &str) -> Vec<String> {
     let import_re =
@@ -39,26 +44,39 @@ pub fn get_imported_components(tsx: &str) -> Vec<String> {
     list
 }
 
+/// Every module specifier imported (or re-exported) by a file, in source order
+pub fn get_import_specifiers(tsx: &str) -> Vec<String> {
+    let spec_re =
+        Regex::new(r#"(?m)^\s*(?:import|export)\s+(?:[^;]*?\s+from\s+)?["']([^"']+)["'];?\s*$"#)
+            .unwrap();
+
+    let mut list = Vec::new();
+    for cap in spec_re.captures_iter(tsx) {
+        let spec = cap[1].to_string();
+        if !list.contains(&spec) {
+            list.push(spec);
+        }
+    }
+    list
+}
+
 pub fn inline_components<'a>(tsx: &'a str, comp_map: &HashMap<&'a str, &'a str>) -> String {
-    // Which external components are imported?
+    // Which external components are imported *and* available for inlining?
     let comps = get_imported_components(tsx); // ← from the earlier helper
-    let wanted: HashSet<&str> = comps.iter().map(String::as_str).collect();
-
-    // ── 1. drop *only* the component imports ────────────────────────────────
-    let import_re = Regex::new(r#"(?m)^(?P<line>\s*import\s+[^;]+?;[ \t]*)$"#).unwrap();
-    let mut stage1 = import_re
-        .replace_all(tsx, |caps: &regex::Captures<'_>| {
-            let line = &caps["line"];
-            if wanted.iter().any(|c| line.contains(c)) {
-                "".to_string()
-            } else {
-                line.to_string()
-            }
-        })
-        .to_string();
+    let wanted: HashSet<&str> = comps
+        .iter()
+        .map(String::as_str)
+        .filter(|c| comp_map.contains_key(c))
+        .collect();
+
+    // ── 1. lift the imports out, dropping *only* the inlined components ─────
+    let (mut imports, body) = take_imports(tsx);
+    imports
+        .bindings
+        .retain(|b| !wanted.contains(b.local.as_str()));
 
     // ── 2. strip every export token/statement throughout the file ───────────
-    stage1 = strip_exports(&stage1);
+    let stage1 = strip_exports(&body);
 
     // ── 3. work out where the principal component ends ──────────────────────
     let main_name = principal_component_name(tsx);
@@ -68,18 +86,112 @@ pub fn inline_components<'a>(tsx: &'a str, comp_map: &HashMap<&'a str, &'a str>)
         stage1.len()
     };
 
-    // ── 4. build the merged block with the requested inline components ──────
-    let mut merged_block = String::from("\n//<merged\n");
-    for (name, code) in comp_map {
-        if wanted.contains(*name) {
-            merged_block.push_str(&strip_exports(code));
-            merged_block.push('\n');
+    // ── 4. build the merged blocks, each one annotated with its origin ──────
+    let mut names: Vec<&&str> = comp_map.keys().filter(|n| wanted.contains(**n)).collect();
+    names.sort();
+
+    // every top-level name already used by the merged file
+    let mut taken: HashSet<String> = top_level_names(&stage1).into_iter().collect();
+    taken.extend(imports.locals());
+
+    let mut seen_blocks: HashSet<String> = HashSet::new();
+    let mut merged_block = String::from("\n");
+    for name in names {
+        let path = component_rel_path(name);
+        // already inlined through another component
+        if !seen_blocks.insert(path.clone()) {
+            continue;
+        }
+
+        let code = drop_duplicate_blocks(&strip_exports(comp_map[*name]), &mut seen_blocks);
+        let (child_imports, child_body) = take_imports(&code);
+        // directives only mean something at the top of the merged file
+        let (_, child_body) = split_directive(&child_body);
+
+        // rename what clashes with the merged file, never the component itself
+        let mut renames: HashMap<String, String> = HashMap::new();
+        for binding in &child_imports.bindings {
+            let clashes = match imports.bound_to(&binding.local) {
+                Some(existing) => {
+                    existing.spec != binding.spec || existing.imported != binding.imported
+                }
+                None => taken.contains(&binding.local),
+            };
+            if clashes {
+                renames.insert(binding.local.clone(), format!("{}_{name}", binding.local));
+            }
+        }
+        for decl in top_level_names(child_body) {
+            if decl != **name && taken.contains(&decl) {
+                renames.insert(decl.clone(), format!("{decl}_{name}"));
+            }
+        }
+
+        for mut binding in child_imports.bindings {
+            if let Some(renamed) = renames.get(&binding.local) {
+                binding.local = renamed.clone();
+            }
+            imports.add(binding);
+        }
+        for spec in &child_imports.side_effects {
+            imports.add_side_effect(spec);
         }
+
+        let child_body = rename_identifiers(child_body, &renames);
+        taken.extend(top_level_names(&child_body));
+        taken.extend(imports.locals());
+
+        merged_block.push_str(&format!("//<merged from=\"{path}\">\n"));
+        merged_block.push_str(child_body.trim_matches('\n'));
+        merged_block.push_str("\n//</merged>\n");
     }
 
-    // ── 5. splice it in ─────────────────────────────────────────────────────
+    // ── 5. splice it in, the deduplicated imports go on top ─────────────────
     let (head, tail) = stage1.split_at(insert_at);
-    format!("{head}{merged_block}{tail}")
+    let (directive, head) = split_directive(head);
+    format!("{directive}{}{head}{merged_block}{tail}", imports.render())
+}
+
+/// Remove the nested `//<merged from=...>` blocks whose file is already part
+/// of the output, registering the new ones in `seen`
+fn drop_duplicate_blocks(code: &str, seen: &mut HashSet<String>) -> String {
+    let open_re = Regex::new(r#"^\s*//<merged from="([^"]+)""#).unwrap();
+    let close_re = Regex::new(r#"^\s*//</merged>"#).unwrap();
+
+    let mut out = String::new();
+    let mut skip_depth = 0usize;
+
+    for line in code.lines() {
+        if skip_depth > 0 {
+            if open_re.is_match(line) {
+                skip_depth += 1;
+            } else if close_re.is_match(line) {
+                skip_depth -= 1;
+            }
+            continue;
+        }
+
+        if let Some(cap) = open_re.captures(line)
+            && !seen.insert(cap[1].to_string())
+        {
+            skip_depth = 1;
+            continue;
+        }
+
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Keep a leading `"use client"` style directive above the hoisted imports
+fn split_directive(code: &str) -> (&str, &str) {
+    let directive_re = Regex::new(r#"^\s*["']use [a-z]+["'];?[ \t]*\n"#).unwrap();
+    match directive_re.find(code) {
+        Some(m) => code.split_at(m.end()),
+        None => ("", code),
+    }
 }
 
 pub fn strip_exports(source: &str) -> String {
@@ -100,7 +212,7 @@ pub fn strip_exports(source: &str) -> String {
         .to_string()
 }
 
-fn principal_component_name(tsx: &str) -> Option<String> {
+pub fn principal_component_name(tsx: &str) -> Option<String> {
     // export default function|class NAME
     let re = Regex::new(r#"export\s+default\s+(?:function|class)\s+([A-Z][A-Za-z0-9_]*)"#).unwrap();
     if let Some(c) = re.captures(tsx) {
@@ -148,32 +260,231 @@ pub fn end_of_component(src: &str, name: &str) -> Option<usize> {
     None
 }
 
-pub fn merge_recurse(tsx: &str, repo: &str) -> String {
-    let imported_components = get_imported_components(tsx);
+/// Path of a mergeable component relative to the repository root
+pub fn component_rel_path(name: &str) -> String {
+    format!("components/{name}.tsx")
+}
 
-    let tsx_without_export = strip_exports(tsx);
+/// Merge the repo-local components imported by `tsx` into it, following their
+/// own imports, along with the files it inlined, the only ones a source map
+/// may point to. Fails on an import cycle and past the merge depth, size or
+/// work limits.
+pub fn merge_with_sources(tsx: &str, repo: &str) -> Result<(String, Vec<String>), LimitDiagnostic> {
+    merge_in_root(tsx, &format!("/etc/compo-doc/tmp/{repo}"))
+}
 
-    if imported_components.len() == 0 {
-        return tsx_without_export.to_string();
-    }
+/// `merge_with_sources` on a clone found at `repo_root`
+pub fn merge_in_root(tsx: &str, repo_root: &str) -> Result<(String, Vec<String>), LimitDiagnostic> {
+    let name = principal_component_name(tsx).unwrap_or("component".to_string());
+    let mut merger = Merger {
+        repo_root,
+        merged: HashMap::new(),
+        stack: Vec::new(),
+        work: 0,
+    };
+    let merged = merger.merge(tsx, &name)?;
+
+    let mut sources: Vec<String> = merger
+        .merged
+        .keys()
+        .map(|c| component_rel_path(c))
+        .collect();
+    sources.sort();
+    Ok((merged, sources))
+}
 
-    let mut hash_map: HashMap<String, String> = HashMap::new();
+/// State of one `merge_with_sources`, a component imported from several places is
+/// merged once
+struct Merger<'a> {
+    repo_root: &'a str,
+    // component -> its merged code
+    merged: HashMap<String, String>,
+    // components being merged, the outermost first
+    stack: Vec<String>,
+    // bytes produced by every merge step so far
+    work: u64,
+}
+
+impl Merger<'_> {
+    fn merge(&mut self, tsx: &str, name: &str) -> Result<String, LimitDiagnostic> {
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            let mut cycle = self.stack[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(LimitDiagnostic::merge_cycle(&cycle));
+        }
+        check_merge_depth(name, self.stack.len())?;
 
-    for (i, component) in imported_components.iter().enumerate() {
-        let path = format!("/etc/compo-doc/tmp/{repo}/components/{component}.tsx");
+        let imported_components = get_imported_components(tsx);
+        let tsx_without_export = strip_exports(tsx);
 
-        let _ = match read_from_file_ut(&path) {
-            Ok(res) => {
-                let merged = merge_recurse(&res, repo);
-                hash_map.insert(component.to_string(), merged)
+        if imported_components.is_empty() {
+            return Ok(tsx_without_export);
+        }
+
+        self.stack.push(name.to_string());
+
+        let mut hash_map: HashMap<String, String> = HashMap::new();
+        for component in imported_components.iter() {
+            if let Some(merged) = self.merged.get(component) {
+                hash_map.insert(component.to_string(), merged.clone());
+                continue;
             }
-            Err(_) => {
+
+            let path = component_rel_path(component);
+            let Ok(res) = read_repo_file(self.repo_root, &path) else {
                 continue;
+            };
+            check_file_size(&path, res.len())?;
+
+            let merged = self.merge(&res, component)?;
+            self.merged.insert(component.to_string(), merged.clone());
+            hash_map.insert(component.to_string(), merged);
+        }
+
+        self.stack.pop();
+
+        let inlined = inline_components(&tsx_without_export, &convert_hash(&hash_map));
+
+        // checked as the merge goes, a wide import graph stops early
+        check_merged_size(name, inlined.len())?;
+        self.work += inlined.len() as u64;
+        check_merge_work(name, self.work)?;
+
+        Ok(inlined)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a clone with a components folder holding `files`
+    fn fake_clone(name: &str, files: &[(String, String)]) -> (std::path::PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("compo-doc-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("components")).unwrap();
+        for (file, code) in files {
+            std::fs::write(dir.join("components").join(file), code).unwrap();
+        }
+        let root = dir.to_string_lossy().to_string();
+        (dir, root)
+    }
+
+    fn component(name: &str, children: &[String]) -> String {
+        let imports: String = children
+            .iter()
+            .map(|c| format!("import {c} from \"./{c}\";\n"))
+            .collect();
+        let uses: String = children.iter().map(|c| format!("<{c} />")).collect();
+        format!("{imports}export default function {name}() {{\n  return <div>{uses}</div>;\n}}\n")
+    }
+
+    #[test]
+    fn merges_a_wide_import_graph_once_per_component() {
+        // every component of a layer imports every component of the next one
+        let (layers, width) = (8, 3);
+        let name = |layer: usize, i: usize| format!("Layer{layer}n{}", (b'a' + i as u8) as char);
+        let mut files = Vec::new();
+        for layer in 1..layers {
+            let children: Vec<String> = if layer + 1 < layers {
+                (0..width).map(|i| name(layer + 1, i)).collect()
+            } else {
+                Vec::new()
+            };
+            for i in 0..width {
+                files.push((
+                    format!("{}.tsx", name(layer, i)),
+                    component(&name(layer, i), &children),
+                ));
             }
-        };
+        }
+        let (dir, root) = fake_clone("merge-dag", &files);
+
+        let entry = component("Root", &(0..width).map(|i| name(1, i)).collect::<Vec<_>>());
+        let started = std::time::Instant::now();
+        let (merged, sources) = merge_in_root(&entry, &root).unwrap();
+        assert_eq!(sources.len(), (layers - 1) * width);
+
+        assert!(started.elapsed().as_secs() < 10);
+        for layer in 1..layers {
+            for i in 0..width {
+                let marker = format!("//<merged from=\"components/{}.tsx\">", name(layer, i));
+                assert_eq!(merged.matches(&marker).count(), 1, "{marker}");
+            }
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reports_an_import_cycle() {
+        let files = vec![
+            (
+                "Alpha.tsx".to_string(),
+                component("Alpha", &["Beta".to_string()]),
+            ),
+            (
+                "Beta.tsx".to_string(),
+                component("Beta", &["Alpha".to_string()]),
+            ),
+        ];
+        let (dir, root) = fake_clone("merge-cycle", &files);
+
+        let err = merge_in_root(&files[0].1, &root).unwrap_err();
+        assert_eq!(err.limit, "merge_cycle");
+        assert_eq!(err.subject, "Alpha -> Beta -> Alpha");
+        let _ = std::fs::remove_dir_all(dir);
     }
 
-    let inlined = inline_components(&tsx_without_export, &convert_hash(&hash_map));
+    #[test]
+    fn stops_past_the_merge_depth() {
+        let depth = crate::utils::limits::limits().max_merge_depth + 2;
+        let name = |i: usize| format!("Chain{i}x");
+        let files: Vec<(String, String)> = (1..depth)
+            .map(|i| {
+                let children = if i + 1 < depth {
+                    vec![name(i + 1)]
+                } else {
+                    vec![]
+                };
+                (format!("{}.tsx", name(i)), component(&name(i), &children))
+            })
+            .collect();
+        let (dir, root) = fake_clone("merge-depth", &files);
+
+        let err = merge_in_root(&component("Head", &[name(1)]), &root).unwrap_err();
+        assert_eq!(err.limit, "max_merge_depth");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn lists_the_imported_components() {
+        let tsx = r#"
+import React, { useState } from "react";
+import Button, { Icon as Glyph } from "./Button";
+import { useTheme, ThemeProvider, AppStore } from "./theme";
+import logo from "./Logo.svg";
+import type { ButtonProps } from "./types";
+"#;
+        assert_eq!(
+            get_imported_components(tsx),
+            vec!["Button", "ButtonProps", "Glyph", "Icon", "React"]
+        );
+    }
 
-    return inlined;
+    #[test]
+    fn finds_the_principal_component() {
+        assert_eq!(
+            principal_component_name("export default function Card() {}").as_deref(),
+            Some("Card")
+        );
+        assert_eq!(
+            principal_component_name("const Card = () => null;\nexport default Card;").as_deref(),
+            Some("Card")
+        );
+        assert_eq!(
+            principal_component_name("export const Badge = () => null;").as_deref(),
+            Some("Badge")
+        );
+        assert_eq!(principal_component_name("export const x = 1;"), None);
+    }
 }
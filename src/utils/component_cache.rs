@@ -0,0 +1,273 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{LazyLock, Mutex},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, Response, StatusCode, header},
+};
+
+// bytes of cached bodies, the oldest responses are dropped past this
+const MAX_CACHED_BYTES: usize = 64 * 1024 * 1024;
+
+struct CachedResponse {
+    content_type: String,
+    body: Bytes,
+    // insertion order, used for the eviction
+    stamp: u64,
+}
+
+#[derive(Default)]
+struct Cache {
+    responses: HashMap<String, CachedResponse>,
+    // stamp -> key, oldest first
+    order: BTreeMap<u64, String>,
+    // sum of the cached body lengths
+    bytes: usize,
+    next_stamp: u64,
+}
+
+impl Cache {
+    /// Cache a body, dropping the oldest responses until it fits in `max_bytes`.
+    /// A body larger than the whole cache is not kept.
+    fn insert(&mut self, key: String, content_type: &str, body: Bytes, max_bytes: usize) {
+        self.remove(&key);
+        if body.len() > max_bytes {
+            return;
+        }
+
+        while self.bytes + body.len() > max_bytes {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(cached) = self.responses.remove(&oldest) {
+                self.bytes -= cached.body.len();
+            }
+        }
+
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.bytes += body.len();
+        self.order.insert(stamp, key.clone());
+        self.responses.insert(
+            key,
+            CachedResponse {
+                content_type: content_type.to_string(),
+                body,
+                stamp,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(cached) = self.responses.remove(key) {
+            self.order.remove(&cached.stamp);
+            self.bytes -= cached.body.len();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.responses.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+}
+
+static CACHE: LazyLock<Mutex<Cache>> = LazyLock::new(Default::default);
+
+/// What a cached response was built from. The commit is part of the key so a
+/// response of a previous sync can never be served.
+pub struct CacheKey {
+    commit: String,
+    // component | bundle
+    route: String,
+    id: String,
+    // query options changing the response
+    options: String,
+}
+
+impl CacheKey {
+    /// `None` before the first sync, nothing is cached without a commit
+    pub fn new(commit: &str, route: &str, id: &str, options: &str) -> Option<Self> {
+        if commit.trim().is_empty() {
+            return None;
+        }
+        Some(CacheKey {
+            commit: commit.trim().to_string(),
+            route: route.to_string(),
+            id: id.to_string(),
+            options: options.to_string(),
+        })
+    }
+
+    fn as_string(&self) -> String {
+        format!(
+            "{}/{}/{}?{}",
+            self.commit, self.route, self.id, self.options
+        )
+    }
+
+    /// The commit followed by a digest of the rest of the key
+    pub fn etag(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        (&self.route, &self.id, &self.options).hash(&mut hasher);
+        format!("\"{}-{:016x}\"", self.commit, hasher.finish())
+    }
+}
+
+/// Whether an `If-None-Match` header matches the etag, weak validators included
+fn matches_if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn build_response(
+    status: StatusCode,
+    etag: &str,
+    content: Option<(&str, Bytes)>,
+) -> Response<Body> {
+    let builder = Response::builder()
+        .status(status)
+        .header(header::ETAG, etag)
+        // always revalidated, the etag changes with every sync
+        .header(header::CACHE_CONTROL, "no-cache");
+
+    match content {
+        Some((content_type, body)) => builder
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body)),
+        None => builder.body(Body::empty()),
+    }
+    .unwrap()
+}
+
+/// 304 when the client already has the response, the cached response when
+/// there is one
+pub fn lookup(key: &CacheKey, headers: &HeaderMap) -> Option<Response<Body>> {
+    let etag = key.etag();
+    if matches_if_none_match(headers, &etag) {
+        return Some(build_response(StatusCode::NOT_MODIFIED, &etag, None));
+    }
+
+    let cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let cached = cache.responses.get(&key.as_string())?;
+    Some(build_response(
+        StatusCode::OK,
+        &etag,
+        Some((&cached.content_type, cached.body.clone())),
+    ))
+}
+
+/// Respond with `body`, cached under `key` when there is one
+pub fn respond(key: Option<&CacheKey>, content_type: &str, body: String) -> Response<Body> {
+    let body = Bytes::from(body);
+    let Some(key) = key else {
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap();
+    };
+
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).insert(
+        key.as_string(),
+        content_type,
+        body.clone(),
+        MAX_CACHED_BYTES,
+    );
+
+    build_response(StatusCode::OK, &key.etag(), Some((content_type, body)))
+}
+
+/// Drop every cached response, called when a sync changes the commit
+pub fn clear() {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn if_none_match(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn keys_the_etag_on_the_commit_and_the_options() {
+        let key =
+            CacheKey::new(" abc\n", "component", "Button.tsx", "inline_assets=false").unwrap();
+        assert!(key.etag().starts_with("\"abc-"));
+
+        let other_commit = CacheKey::new("def", "component", "Button.tsx", "inline_assets=false");
+        let other_options = CacheKey::new("abc", "component", "Button.tsx", "inline_assets=true");
+        assert_ne!(key.etag(), other_commit.unwrap().etag());
+        assert_ne!(key.etag(), other_options.unwrap().etag());
+        assert!(CacheKey::new(" ", "component", "Button.tsx", "").is_none());
+    }
+
+    #[test]
+    fn answers_304_to_a_matching_if_none_match() {
+        let key = CacheKey::new("abc", "component", "Etag.tsx", "").unwrap();
+        let etag = key.etag();
+
+        for value in [
+            etag.clone(),
+            format!("W/{etag}"),
+            format!("\"x\", {etag}"),
+            "*".into(),
+        ] {
+            let response = lookup(&key, &if_none_match(&value)).unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        }
+
+        // nothing cached yet and a stale etag
+        assert!(lookup(&key, &if_none_match("\"abc-0\"")).is_none());
+    }
+
+    #[test]
+    fn serves_the_cached_response_of_the_same_commit_only() {
+        let key = CacheKey::new("abc", "bundle", "Commit.tsx", "cdn=x").unwrap();
+        let response = respond(Some(&key), "text/javascript", "export {};".to_string());
+        assert_eq!(response.headers()[header::ETAG], key.etag().as_str());
+
+        let hit = lookup(&key, &HeaderMap::new()).unwrap();
+        assert_eq!(hit.status(), StatusCode::OK);
+        assert_eq!(hit.headers()[header::CONTENT_TYPE], "text/javascript");
+
+        let next_commit = CacheKey::new("def", "bundle", "Commit.tsx", "cdn=x").unwrap();
+        assert!(lookup(&next_commit, &HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn evicts_the_oldest_bodies_past_the_byte_bound() {
+        let mut cache = Cache::default();
+        cache.insert("a".into(), "text/plain", Bytes::from("aaaa"), 10);
+        cache.insert("b".into(), "text/plain", Bytes::from("bbbb"), 10);
+        cache.insert("c".into(), "text/plain", Bytes::from("cccc"), 10);
+        assert!(!cache.responses.contains_key("a"));
+        assert!(cache.responses.contains_key("b") && cache.responses.contains_key("c"));
+        assert_eq!(cache.bytes, 8);
+
+        // replacing a key does not count it twice
+        cache.insert("c".into(), "text/plain", Bytes::from("cc"), 10);
+        assert_eq!((cache.bytes, cache.responses.len()), (6, 2));
+
+        // too large for the whole cache, never kept
+        cache.insert("d".into(), "text/plain", Bytes::from("d".repeat(11)), 10);
+        assert!(!cache.responses.contains_key("d"));
+        assert_eq!(cache.bytes, 6);
+
+        cache.clear();
+        assert_eq!((cache.bytes, cache.order.len()), (0, 0));
+    }
+}
@@ -0,0 +1,297 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    structs::{ConfigContent, FieldError},
+    utils::{read_from_file_ut, write_to_file_ut},
+};
+
+pub const ALLOWED_HOSTS_PATH: &str = "/etc/compo-doc/config/allowed_hosts.json";
+
+const MAX_REPO_URL_LEN: usize = 512;
+const MAX_BRANCH_LEN: usize = 255;
+const MAX_HOSTNAME_LEN: usize = 253;
+
+/// Hosts repositories can be cloned from, edited by the admins
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AllowedHosts {
+    pub hosts: Vec<String>,
+}
+
+impl Default for AllowedHosts {
+    fn default() -> Self {
+        AllowedHosts {
+            hosts: vec!["github.com".to_string()],
+        }
+    }
+}
+
+impl AllowedHosts {
+    /// The saved allowlist, github.com only when none was saved
+    pub fn load() -> Self {
+        let Ok(content) = read_from_file_ut(ALLOWED_HOSTS_PATH) else {
+            return AllowedHosts::default();
+        };
+        match serde_json::from_str(&content) {
+            Ok(hosts) => hosts,
+            Err(err) => {
+                println!("invalid {ALLOWED_HOSTS_PATH}, using the default hosts : {err}");
+                AllowedHosts::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        // write_to_file_ut appends, start from an empty file
+        let _ = std::fs::remove_file(ALLOWED_HOSTS_PATH);
+        write_to_file_ut(ALLOWED_HOSTS_PATH, &content).map(|_| ())
+    }
+
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.hosts.is_empty() {
+            errors.push(field_error("hosts", "at least one host is needed"));
+        }
+        for (i, host) in self.hosts.iter().enumerate() {
+            if !is_valid_hostname(host) {
+                errors.push(field_error(
+                    &format!("hosts[{i}]"),
+                    &format!("{host:?} is not a valid host name"),
+                ));
+            }
+        }
+        errors
+    }
+}
+
+/// A repository configuration that passed the validation
+#[derive(Debug, Clone)]
+pub struct ValidatedRepo {
+    // https url ending with `.git`
+    pub url: String,
+    // repository name, the folder it is cloned in
+    pub name: String,
+    pub branch: String,
+}
+
+/// Check the repository url and the branch, every problem is reported with the
+/// field it comes from
+pub fn validate_config(
+    config: &ConfigContent,
+    allowed_hosts: &AllowedHosts,
+) -> Result<ValidatedRepo, Vec<FieldError>> {
+    let mut errors = Vec::new();
+
+    let repo = validate_repo_url(config.repo.trim(), allowed_hosts);
+    if let Err(message) = &repo {
+        errors.push(field_error("repo", message));
+    }
+    if let Err(message) = validate_branch(&config.branch) {
+        errors.push(field_error("branch", &message));
+    }
+
+    match repo {
+        Ok((url, name)) if errors.is_empty() => Ok(ValidatedRepo {
+            url,
+            name,
+            branch: config.branch.clone(),
+        }),
+        _ => Err(errors),
+    }
+}
+
+/// `https://<allowed host>/<owner>/<name>[.git]`, returns the url with its
+/// `.git` suffix and the repository name
+fn validate_repo_url(url: &str, allowed_hosts: &AllowedHosts) -> Result<(String, String), String> {
+    if url.is_empty() {
+        return Err("the repository url is required".to_string());
+    }
+    if url.len() > MAX_REPO_URL_LEN {
+        return Err(format!("at most {MAX_REPO_URL_LEN} characters"));
+    }
+
+    let Some(rest) = url.strip_prefix("https://") else {
+        return Err("only https:// urls are accepted".to_string());
+    };
+
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if host.contains(['@', ':']) {
+        return Err("credentials and ports are not accepted in the url".to_string());
+    }
+    let host = host.to_lowercase();
+    if !allowed_hosts
+        .hosts
+        .iter()
+        .any(|h| h.eq_ignore_ascii_case(&host))
+    {
+        return Err(format!(
+            "{host} is not an allowed host ({})",
+            allowed_hosts.hosts.join(", ")
+        ));
+    }
+
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let segment_re = Regex::new(r"^[A-Za-z0-9][A-Za-z0-9._-]{0,99}$").unwrap();
+    let segments: Vec<&str> = path.split('/').collect();
+    let [owner, name] = segments[..] else {
+        return Err("expected https://<host>/<owner>/<repository>".to_string());
+    };
+    for segment in [owner, name] {
+        if !segment_re.is_match(segment) || segment.contains("..") {
+            return Err(format!(
+                "{segment:?} is not a valid owner or repository name"
+            ));
+        }
+    }
+
+    Ok((
+        format!("https://{host}/{owner}/{name}.git"),
+        name.to_string(),
+    ))
+}
+
+/// Branch names following `git check-ref-format --branch`, `/` separates the
+/// components of the name
+fn validate_branch(branch: &str) -> Result<(), String> {
+    if branch.is_empty() {
+        return Err("the branch is required".to_string());
+    }
+    if branch.len() > MAX_BRANCH_LEN {
+        return Err(format!("at most {MAX_BRANCH_LEN} characters"));
+    }
+    if branch.starts_with('-') {
+        return Err("can not start with -".to_string());
+    }
+    if branch.starts_with('/') || branch.ends_with('/') || branch.contains("//") {
+        return Err("can not start or end with / or contain //".to_string());
+    }
+    if branch.ends_with('.') {
+        return Err("can not end with .".to_string());
+    }
+    if branch
+        .split('/')
+        .any(|component| component.starts_with('.') || component.ends_with(".lock"))
+    {
+        return Err("no part between / can start with . or end with .lock".to_string());
+    }
+    if branch == "@" || branch.contains("..") || branch.contains("@{") {
+        return Err("can not be @ or contain .. or @{".to_string());
+    }
+    if branch
+        .chars()
+        .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    {
+        return Err("can not contain spaces, control characters or ~ ^ : ? * [ \\".to_string());
+    }
+
+    Ok(())
+}
+
+fn is_valid_hostname(host: &str) -> bool {
+    let label_re = Regex::new(r"^[A-Za-z0-9]([A-Za-z0-9-]{0,61}[A-Za-z0-9])?$").unwrap();
+    !host.is_empty()
+        && host.len() <= MAX_HOSTNAME_LEN
+        && host.contains('.')
+        && host.split('.').all(|label| label_re.is_match(label))
+}
+
+fn field_error(field: &str, message: &str) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(repo: &str, branch: &str) -> Result<ValidatedRepo, Vec<FieldError>> {
+        let config = ConfigContent {
+            repo: repo.to_string(),
+            branch: branch.to_string(),
+            dry_run: false,
+        };
+        validate_config(&config, &AllowedHosts::default())
+    }
+
+    fn repo_error(repo: &str) -> String {
+        validate_repo_url(repo, &AllowedHosts::default()).unwrap_err()
+    }
+
+    #[test]
+    fn normalizes_the_repository_url() {
+        let repo = validate(" https://GitHub.com/owner/my-lib ", "main").unwrap();
+        assert_eq!(repo.url, "https://github.com/owner/my-lib.git");
+        assert_eq!(repo.name, "my-lib");
+        assert_eq!(repo.branch, "main");
+    }
+
+    #[test]
+    fn refuses_the_urls_leaving_the_allowed_hosts() {
+        assert!(repo_error("http://github.com/owner/lib").contains("https"));
+        assert!(repo_error("file:///etc/passwd").contains("https"));
+        assert!(repo_error("https://evil.com/owner/lib").contains("not an allowed host"));
+        assert!(repo_error("https://github.com.evil.com/owner/lib").contains("not an allowed"));
+        assert!(repo_error("https://user:pw@github.com/owner/lib").contains("credentials"));
+        assert!(repo_error("https://github.com:8443/owner/lib").contains("ports"));
+        assert!(repo_error("https://github.com/owner/../lib").contains("expected"));
+        assert!(repo_error("https://github.com/owner/..").contains("not a valid"));
+        assert!(repo_error("https://github.com/-upload-pack=x/lib").contains("not a valid"));
+        assert!(repo_error("https://github.com/owner/lib;rm").contains("not a valid"));
+    }
+
+    #[test]
+    fn refuses_the_invalid_branches() {
+        for branch in [
+            "",
+            "-b",
+            ".hidden",
+            "a..b",
+            "main.lock",
+            "a b",
+            "a~1",
+            "@",
+            "a@{1}",
+            "main.",
+            "a\\b",
+            "a\u{7f}",
+            "/main",
+            "main/",
+            "feature//x",
+            "feature/.x",
+            "feature/x.lock",
+        ] {
+            let errors = validate("https://github.com/owner/lib", branch).unwrap_err();
+            assert_eq!(errors[0].field, "branch", "{branch:?}");
+        }
+        for branch in ["release-1.2", "feature/login", "user/jo/fix.2", "é"] {
+            assert!(
+                validate("https://github.com/owner/lib", branch).is_ok(),
+                "{branch:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_every_field_at_once() {
+        let errors = validate("", "").unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["repo", "branch"]);
+    }
+
+    #[test]
+    fn validates_the_allowed_hosts() {
+        let hosts = AllowedHosts {
+            hosts: vec![
+                "gitlab.example.com".to_string(),
+                "localhost".to_string(),
+                "a_b.com".to_string(),
+            ],
+        };
+        let fields: Vec<String> = hosts.validate().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["hosts[1]", "hosts[2]"]);
+        assert_eq!(AllowedHosts { hosts: Vec::new() }.validate().len(), 1);
+    }
+}
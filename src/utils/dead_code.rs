@@ -0,0 +1,222 @@
+use regex::Regex;
+
+use crate::utils::{
+    code_merge::principal_component_name,
+    examples::{CODE_FILE_EXTENSIONS, is_test_file},
+    jsx_usage::{find_jsx_usages, imports_component},
+    search::extract_props,
+    storybook::is_story_file,
+    structs::{DeadCodeReport, DeadProp, UnusedComponent},
+    type_extractor::find_used_type,
+    utils::read_repo_files,
+};
+
+/// Components of `component_dir` never imported by the rest of the repository,
+/// and props neither read by their component nor passed at any call site
+pub fn dead_code_report(repo_root: &str, component_dir: &str) -> DeadCodeReport {
+    let prefix = format!("{}/", component_dir.trim_matches('/'));
+
+    // read every source once, the checks below run over all of them
+    let files = read_repo_files(repo_root, &CODE_FILE_EXTENSIONS);
+
+    let mut report = DeadCodeReport::default();
+    // `{...props}` forwards the props, any of them may be read down the tree
+    let spread_re = Regex::new(r"\{\s*\.\.\.\s*[A-Za-z_$][\w$]*\s*\}").unwrap();
+
+    for (path, code) in files.iter().filter(|(f, _)| f.starts_with(&prefix)) {
+        let name = principal_component_name(code).unwrap_or(file_stem(path).to_string());
+
+        // stories and tests document a component, they do not make it used
+        let mut importers = Vec::new();
+        let mut documented_by = Vec::new();
+        for (file, other) in &files {
+            if file == path || !imports_component(other, file, path, &name) {
+                continue;
+            }
+            if is_story_file(file) || is_test_file(file) {
+                documented_by.push(file.clone());
+            } else {
+                importers.push(file.clone());
+            }
+        }
+
+        if importers.is_empty() {
+            report.unused_components.push(UnusedComponent {
+                file: path.clone(),
+                name: name.clone(),
+                documented_by,
+            });
+        }
+
+        let props = extract_props(code);
+        if props.is_empty() {
+            continue;
+        }
+
+        let usages: Vec<_> = files
+            .iter()
+            .filter(|(file, _)| file != path)
+            .flat_map(|(file, other)| find_jsx_usages(other, file, path, &name))
+            .collect();
+
+        // a spread at a call site may pass anything
+        if usages.iter().any(|u| u.has_spread) {
+            continue;
+        }
+
+        let body = strip_props_type(code);
+        let forwards_props = spread_re.is_match(&body);
+        if forwards_props {
+            continue;
+        }
+        let destructured = destructured_params(&body);
+
+        for prop in props {
+            let passed = usages
+                .iter()
+                .any(|u| u.props.iter().any(|p| p.name == prop.name));
+            if passed || is_read(&body, &prop.name, &destructured) {
+                continue;
+            }
+
+            report.dead_props.push(DeadProp {
+                file: path.clone(),
+                component: name.clone(),
+                prop: prop.name,
+            });
+        }
+    }
+
+    report
+}
+
+fn file_stem(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.split('.').next().unwrap_or(name)
+}
+
+/// The component source without its imports and the declaration of its props
+/// type, so the prop names left are the ones the code actually uses
+fn strip_props_type(code: &str) -> String {
+    let import_re = Regex::new(r#"(?m)^\s*import\s[^;]*?["'][^"']+["'];?\s*$"#).unwrap();
+    let code = &*import_re.replace_all(code, "");
+
+    let Ok(Some(type_name)) = find_used_type(code) else {
+        return code.to_string();
+    };
+
+    let decl_re = Regex::new(&format!(
+        r"\b(?:interface|type)\s+{}\b",
+        regex::escape(&type_name)
+    ))
+    .unwrap();
+    let Some(start) = decl_re.find(code).map(|m| m.start()) else {
+        return code.to_string();
+    };
+    let Some(open) = code[start..].find('{').map(|i| start + i) else {
+        return code.to_string();
+    };
+
+    let mut depth = 0;
+    for (i, c) in code[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    let end = open + i + 1;
+                    return format!("{}{}", &code[..start], &code[end..]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    code.to_string()
+}
+
+/// Names destructured in the component parameters (`({ label, size = "md" })`)
+fn destructured_params(code: &str) -> Vec<String> {
+    let params_re = Regex::new(r"\(\s*\{([^{}]*)\}\s*(?::[^)]*)?\)").unwrap();
+    let Some(cap) = params_re.captures(code) else {
+        return Vec::new();
+    };
+
+    cap[1]
+        .split(',')
+        .filter_map(|p| {
+            let name = p.split([':', '=']).next()?.trim();
+            (!name.is_empty() && !name.starts_with("...")).then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// The prop is used somewhere else than in its own destructuring
+fn is_read(body: &str, prop: &str, destructured: &[String]) -> bool {
+    let word_re = Regex::new(&format!(r"\b{}\b", regex::escape(prop))).unwrap();
+    let occurrences = word_re.find_iter(body).count();
+
+    if destructured.iter().any(|d| d == prop) {
+        occurrences > 1
+    } else {
+        occurrences > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_the_imports_before_counting_a_usage() {
+        let root = std::env::temp_dir().join(format!("compo-doc-dead-code-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("components")).unwrap();
+        std::fs::create_dir_all(root.join("pages")).unwrap();
+        std::fs::create_dir_all(root.join("stories")).unwrap();
+
+        let files = [
+            (
+                "components/Button.tsx",
+                "interface ButtonProps { variant: string; size: number }\nexport const Button = ({ variant, size }: ButtonProps) => <button className={variant} />;\n",
+            ),
+            (
+                "components/Card.tsx",
+                "export const Card = () => <div />;\n",
+            ),
+            (
+                "stories/Card.stories.tsx",
+                "import { Card } from \"../components/Card\";\nexport const Default = () => <Card />;\n",
+            ),
+            // the size passed to the other library's button is not the one of ours
+            (
+                "pages/Page.tsx",
+                "import { Button } from \"@mui/material\";\nimport { Button as Local } from \"../components/Button\";\nexport const Page = () => <><Button size={2} /><Local variant=\"a\" /></>;\n",
+            ),
+        ];
+        for (path, code) in files {
+            std::fs::write(root.join(path), code).unwrap();
+        }
+
+        let report = dead_code_report(root.to_str().unwrap(), "components");
+
+        let unused: Vec<(&str, &[String])> = report
+            .unused_components
+            .iter()
+            .map(|c| (c.name.as_str(), c.documented_by.as_slice()))
+            .collect();
+        assert_eq!(
+            unused,
+            [("Card", &["stories/Card.stories.tsx".to_string()][..])]
+        );
+
+        let dead: Vec<(&str, &str)> = report
+            .dead_props
+            .iter()
+            .map(|p| (p.component.as_str(), p.prop.as_str()))
+            .collect();
+        assert_eq!(dead, [("Button", "size")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
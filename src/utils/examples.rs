@@ -0,0 +1,238 @@
+use crate::utils::{
+    jsx_usage::{JsxProp, find_jsx_usages},
+    storybook::{CsfFile, component_csf, is_story_file, story_id},
+    structs::{ExampleProp, UsageExample},
+};
+
+// real call sites are plenty in big apps, a few are enough for the docs
+const MAX_CALL_SITES: usize = 10;
+
+pub const CODE_FILE_EXTENSIONS: [&str; 4] = ["tsx", "jsx", "ts", "js"];
+
+pub fn is_test_file(path: &str) -> bool {
+    path.contains(".test.") || path.contains(".spec.") || path.contains("__tests__/")
+}
+
+/// Gather usage snippets of a component from the stories, the tests and the
+/// rest of the repository files (path, code). `link_base` is prefixed to the
+/// file paths to link them (e.g. the repository at the synced commit).
+pub fn component_examples(
+    files: &[(String, String)],
+    component_path: &str,
+    component_name: &str,
+    csf_files: &[(String, CsfFile)],
+    link_base: Option<&str>,
+) -> Vec<UsageExample> {
+    let mut stories = Vec::new();
+    let mut tests = Vec::new();
+    let mut call_sites = Vec::new();
+
+    let link = |file: &str, line: usize| link_base.map(|base| format!("{base}{file}#L{line}"));
+
+    // every story of the component is an example
+    for (file, csf) in component_csf(csf_files, component_name) {
+        for story in &csf.stories {
+            stories.push(UsageExample {
+                kind: "story".to_string(),
+                story_id: csf
+                    .title
+                    .as_ref()
+                    .map(|title| story_id(title, &story.export_name)),
+                title: Some(match &csf.title {
+                    Some(title) => format!("{title} / {}", story.name),
+                    None => story.name.clone(),
+                }),
+                link: link(file, story.line),
+                file: file.clone(),
+                line: story.line,
+                code: story.code.clone(),
+                props: example_props(story.args.clone()),
+                has_spread: false,
+            });
+        }
+    }
+
+    for (file, code) in files {
+        if file == component_path || is_story_file(file) {
+            continue;
+        }
+
+        let is_test = is_test_file(file);
+        if !is_test && call_sites.len() >= MAX_CALL_SITES {
+            continue;
+        }
+
+        for usage in find_jsx_usages(code, file, component_path, component_name) {
+            let example = UsageExample {
+                kind: if is_test { "test" } else { "call_site" }.to_string(),
+                title: None,
+                link: link(file, usage.line),
+                file: file.clone(),
+                line: usage.line,
+                code: usage.code,
+                props: example_props(usage.props),
+                has_spread: usage.has_spread,
+                story_id: None,
+            };
+
+            if is_test {
+                tests.push(example);
+            } else if call_sites.len() < MAX_CALL_SITES {
+                call_sites.push(example);
+            }
+        }
+    }
+
+    // the curated examples first
+    stories.extend(tests);
+    stories.extend(call_sites);
+    stories
+}
+
+pub fn example_props(props: Vec<JsxProp>) -> Vec<ExampleProp> {
+    props
+        .into_iter()
+        .map(|p| ExampleProp {
+            name: p.name,
+            value: p.value,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::storybook::parse_csf;
+
+    const TARGET: &str = "components/Button.tsx";
+
+    const STORIES: &str = r#"import { Button } from "./Button";
+
+export default { title: "Forms/Button", component: Button };
+
+export const Primary = { args: { label: "Save", size: "md" } };
+"#;
+
+    fn files() -> Vec<(String, String)> {
+        [
+            (
+                TARGET,
+                "export const Button = (props) => <button {...props} />;\n",
+            ),
+            ("components/Button.stories.tsx", STORIES),
+            (
+                "components/Button.test.tsx",
+                r#"import { Button } from "./Button";
+it("renders", () => {
+  render(<Button label="Test" />);
+});
+"#,
+            ),
+            (
+                "pages/Home.tsx",
+                r#"import { Button } from "../components/Button";
+export const Home = () => (
+  <main>
+    <Button label="Go" {...rest} />
+  </main>
+);
+"#,
+            ),
+            (
+                "pages/Other.tsx",
+                r#"import { Button } from "@mui/material";
+export const Other = () => <Button label="Not ours" />;
+"#,
+            ),
+        ]
+        .into_iter()
+        .map(|(file, code)| (file.to_string(), code.to_string()))
+        .collect()
+    }
+
+    fn csf_files() -> Vec<(String, CsfFile)> {
+        vec![(
+            "components/Button.stories.tsx".to_string(),
+            parse_csf(STORIES).unwrap(),
+        )]
+    }
+
+    #[test]
+    fn orders_the_stories_the_tests_and_the_call_sites() {
+        let examples = component_examples(&files(), TARGET, "Button", &csf_files(), None);
+
+        let found: Vec<(&str, &str, usize)> = examples
+            .iter()
+            .map(|e| (e.kind.as_str(), e.file.as_str(), e.line))
+            .collect();
+        // the component itself, the story file and another Button are skipped
+        assert_eq!(
+            found,
+            [
+                ("story", "components/Button.stories.tsx", 5),
+                ("test", "components/Button.test.tsx", 3),
+                ("call_site", "pages/Home.tsx", 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_snippets_and_the_props_of_each_example() {
+        let examples = component_examples(
+            &files(),
+            TARGET,
+            "Button",
+            &csf_files(),
+            Some("https://github.com/owner/lib/blob/abc/"),
+        );
+
+        let story = &examples[0];
+        assert_eq!(story.title.as_deref(), Some("Forms/Button / Primary"));
+        assert_eq!(story.story_id.as_deref(), Some("forms-button--primary"));
+        assert!(story.code.contains(r#"label: "Save""#));
+        let args: Vec<(&str, Option<&str>)> = story
+            .props
+            .iter()
+            .map(|p| (p.name.as_str(), p.value.as_deref()))
+            .collect();
+        assert_eq!(
+            args,
+            [("label", Some(r#""Save""#)), ("size", Some(r#""md""#))]
+        );
+
+        let test = &examples[1];
+        assert_eq!(test.code, r#"<Button label="Test" />"#);
+        assert!(!test.has_spread);
+
+        let call_site = &examples[2];
+        assert_eq!(call_site.code, r#"<Button label="Go" {...rest} />"#);
+        assert!(call_site.has_spread);
+        assert_eq!(
+            call_site.link.as_deref(),
+            Some("https://github.com/owner/lib/blob/abc/pages/Home.tsx#L4")
+        );
+    }
+
+    #[test]
+    fn caps_the_call_sites_but_not_the_tests() {
+        let mut files = files();
+        for i in 0..MAX_CALL_SITES + 2 {
+            files.push((
+                format!("pages/Page{i}.tsx"),
+                "import Button from \"../components/Button\";\nexport const P = () => <Button />;\n"
+                    .to_string(),
+            ));
+            files.push((
+                format!("components/__tests__/Button{i}.tsx"),
+                "import { Button } from \"../Button\";\nrender(<Button />);\n".to_string(),
+            ));
+        }
+
+        let examples = component_examples(&files, TARGET, "Button", &[], None);
+        let count = |kind: &str| examples.iter().filter(|e| e.kind == kind).count();
+        assert_eq!(count("story"), 0);
+        assert_eq!(count("call_site"), MAX_CALL_SITES);
+        assert_eq!(count("test"), MAX_CALL_SITES + 3);
+        assert!(is_test_file("a/Button.spec.ts") && !is_test_file("a/Button.tsx"));
+    }
+}
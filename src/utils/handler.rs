@@ -1,27 +1,450 @@
-use axum::{Extension, Json, extract::Path, http::StatusCode, response::IntoResponse};
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::{Path, Query},
+    http::{HeaderMap, Response, StatusCode},
+    response::IntoResponse,
+};
 
 use crate::utils::{
-    code_merge::{get_imported_components, merge_recurse},
+    api_diff::diff_refs,
+    audit::{read_events, record},
+    auth::{Caller, Role},
+    bundler::{ALLOWED_CDN_BASES, DEFAULT_CDN_BASE, allowed_cdn_base, bundle_component},
+    code_merge::{merge_with_sources, principal_component_name},
+    component_cache::{CacheKey, clear as clear_component_cache, lookup, respond},
+    config_validation::{AllowedHosts, validate_config},
+    dead_code::dead_code_report,
+    history::{component_history, library_changelog},
     http_utils::json_response_builder,
-    structs::{ComponentModel, JsonResponse},
+    index_store::{clear_index, load_index, load_merged, restore_index, save_index, save_merged},
+    indexer::index_component,
+    limits::{check_component_count, check_file_size},
+    npm_deps::component_npm_dependencies,
+    rate_limit::{WorkSlots, busy},
+    search::{MAX_QUERY_LEN, extract_props},
+    source_map::build_source_map,
+    structs::{
+        ComponentMeta, ComponentModel, ComponentQuery, ComponentUsages, DeadCodeReport,
+        JsonResponse, RepoConfig,
+    },
+    style_collector::collect_component_styles,
+    sync_pipeline::{SYNC_LOCK, build_index, sync_progress, update_index},
     type_extractor::find_used_type,
+    usages::component_usages,
 };
 
+use crate::AppError;
+
 use super::{
-    structs::{ComponentsList, ConfigContent, SharedState},
-    type_extractor::TypeExtractor,
+    structs::{
+        ApiDiffReport, AuditEvent, AuditQuery, BundleQuery, CompareQuery, ComponentGroup,
+        ComponentStatus, ComponentsList, ConfigContent, FieldError, HistoryEntry, HistoryQuery,
+        LimitDiagnostic, ListQuery, Pagination, SearchHit, SearchQuery, SharedState, StoredIndex,
+        SyncProgress, SyncStatus,
+    },
     utils::{
-        execute_commande, extract_repo_info, get_new_repo_ver, list_dir_contents,
-        read_from_file_ut, write_to_file_ut,
+        component_file_path, extract_repo_info, get_new_repo_ver, git_output, list_dir_contents,
+        read_from_file_ut, read_repo_config, update_repo_ver, write_to_file_ut,
     },
 };
 
 // API handlers
-pub async fn list_components(state: Extension<SharedState>) -> impl IntoResponse {
-    let mut state = state.write().await;
+pub async fn list_components(
+    state: Extension<SharedState>,
+    caller: Extension<Caller>,
+    slots: Extension<WorkSlots>,
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
+    // readers are served the cached index, only maintainers pull the repository
+    if caller.role < Role::Maintainer {
+        let state = state.read().await;
+        return components_list_response(&state.comp_liste, &query);
+    }
+
+    let Some(_sync_slot) = slots.sync() else {
+        return busy();
+    };
+
+    if let Err((status, message)) = audited_sync(&state, &caller.name, false).await {
+        return json_response_builder(status, JsonResponse::<String>::make_error(message));
+    }
+
+    let state = state.read().await;
+    components_list_response(&state.comp_liste, &query)
+}
+
+pub async fn sync_repository(
+    state: Extension<SharedState>,
+    caller: Extension<Caller>,
+    slots: Extension<WorkSlots>,
+) -> impl IntoResponse {
+    let Some(_sync_slot) = slots.sync() else {
+        return busy();
+    };
+
+    let result = audited_sync(&state, &caller.name, true).await;
+    let state = state.read().await;
+
+    match result {
+        Ok(changed) => json_response_builder(
+            StatusCode::OK,
+            JsonResponse::<SyncStatus>::make_success(
+                if changed {
+                    "components synced"
+                } else {
+                    "components already up to date"
+                },
+                SyncStatus {
+                    commit: state.curr_ver.trim().to_string(),
+                    components: state.comp_liste.len(),
+                    changed,
+                    diagnostics: state.diagnostics.clone(),
+                    duration_ms: if changed {
+                        sync_progress().duration_ms
+                    } else {
+                        None
+                    },
+                },
+            ),
+        ),
+        Err((status, message)) => {
+            json_response_builder(status, JsonResponse::<String>::make_error(message))
+        }
+    }
+}
+
+/// Phase of the running sync, or the per file timings of the last one
+pub async fn get_sync_progress() -> impl IntoResponse {
+    json_response_builder(
+        StatusCode::OK,
+        JsonResponse::<SyncProgress>::make_success("sync progress", sync_progress()),
+    )
+}
+
+/// Run a sync and write it to the audit log. The syncs finding nothing new are
+/// only logged when explicitly asked for, listing the components runs one too.
+async fn audited_sync(
+    state: &SharedState,
+    actor: &str,
+    explicit: bool,
+) -> Result<bool, (StatusCode, String)> {
+    let started = Instant::now();
+    let result = sync_components(state).await;
 
+    if explicit || !matches!(result, Ok(false)) {
+        let mut event = AuditEvent::new("sync", actor, result.is_ok());
+        let commit = state.read().await.curr_ver.trim().to_string();
+        event.commit = Some(commit).filter(|c| !c.is_empty());
+        event.duration_ms = Some(started.elapsed().as_millis() as u64);
+        event.changed = result.as_ref().ok().copied();
+        event.error = result.as_ref().err().map(|(_, message)| message.clone());
+        record(event);
+    }
+
+    result
+}
+
+/// Pull the configured repository when its branch moved and rebuild the index,
+/// returns whether anything changed. The index is built without holding the
+/// state, which only gets locked to swap it in.
+async fn sync_components(state: &SharedState) -> Result<bool, (StatusCode, String)> {
+    // one sync at a time
+    let _sync_guard = SYNC_LOCK.lock().await;
+
+    let curr_ver = state.read().await.curr_ver.clone();
+    // git and the parsing block, the other requests move to the other workers
+    let Some(stored) = tokio::task::block_in_place(|| pull_and_index(&curr_ver))? else {
+        return Ok(false);
+    };
+
+    // the whole index replaces the previous one at once
+    {
+        let mut state = state.write().await;
+        restore_index(&mut state, stored.clone());
+        println!(
+            "search index : {} components indexed",
+            state.search_index.len()
+        );
+    }
+
+    // the cached responses belong to the previous commit
+    clear_component_cache();
+
+    // a restart serves this index until the branch moves
+    if let Err(err) = save_index(&stored) {
+        println!("could not save the index of {} : {err}", stored.repo);
+    }
+
+    Ok(true)
+}
+
+/// Clone the branch when it moved away from `curr_ver` and index it, `None`
+/// when it did not move
+fn pull_and_index(curr_ver: &str) -> Result<Option<StoredIndex>, (StatusCode, String)> {
     // read from config path to get repo link
-    let config_content = match read_from_file_ut("/etc/compo-doc/config/config") {
+    let repo_config = match read_repo_config() {
+        Ok(res) => res,
+        Err(err) => {
+            println!("Error : {}", err);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "could not write the repository to file please try again later".to_string(),
+            ));
+        }
+    };
+
+    let repo_url = repo_config.url.as_str();
+    let (username, repo, branch) = match extract_repo_info(&repo_config) {
+        Some(res) => res,
+        None => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "could not parse repository url".to_string(),
+            ));
+        }
+    };
+
+    let holding_folder = format!("/etc/compo-doc/tmp/{}/components", repo);
+
+    // the full ref, `feature/x` alone also matches `refs/heads/other/feature/x`
+    let branch_ref = format!("refs/heads/{branch}");
+    let fetched_version =
+        match git_output("/etc/compo-doc/tmp", &["ls-remote", repo_url, &branch_ref]) {
+            Ok(v) => v.trim().split("refs").next().unwrap().trim().to_string(),
+            Err(err) => {
+                print!("{}", err);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Error while checking repo version".to_string(),
+                ));
+            }
+        };
+    println!("curr : {}", curr_ver);
+    println!("fetched : {}", &fetched_version);
+
+    if fetched_version == curr_ver {
+        return Ok(None);
+    }
+
+    // the indexed clone is moved forward, only what changed is indexed again
+    let previous = load_index(repo, curr_ver.trim());
+    let changed = previous.as_ref().and_then(|_| {
+        match update_repo_ver(repo_url, repo, branch, curr_ver.trim()) {
+            Ok(changed) => Some(changed),
+            Err(err) => {
+                println!("cloning again, could not update the clone : {}", err.trim());
+                None
+            }
+        }
+    });
+
+    if changed.is_none() {
+        let _ = match get_new_repo_ver(repo_url, repo, branch) {
+            Ok(r) => r,
+            Err(err) => {
+                println!("Error occured while cloning repo: {} ", err);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("could not clone the repository : {}", err.trim()),
+                ));
+            }
+        };
+    }
+
+    let files_liste = match list_dir_contents(&holding_folder) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("Error : {}", err);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "could not read the components liste (either no repo found or no components)"
+                    .to_string(),
+            ));
+        }
+    };
+
+    if let Err(diagnostic) = check_component_count(files_liste.len()) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, diagnostic.message()));
+    }
+
+    // the examples link to the files at the synced commit
+    let link_base = format!("https://github.com/{username}/{repo}/blob/{fetched_version}/");
+    let stored = match (previous, changed) {
+        (Some(previous), Some(changed)) => update_index(
+            previous,
+            &fetched_version,
+            &files_liste,
+            &changed,
+            Some(&link_base),
+        ),
+        _ => build_index(repo, &fetched_version, &files_liste, Some(&link_base)),
+    };
+    Ok(Some(stored))
+}
+
+/// 422 explaining which limit the component went over
+fn limit_response(diagnostic: LimitDiagnostic) -> Response<Body> {
+    json_response_builder(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        JsonResponse::<String>::make_error(diagnostic.message()).with_errors(vec![FieldError {
+            field: diagnostic.limit.clone(),
+            message: diagnostic.message(),
+        }]),
+    )
+}
+
+/// Filter, sort and paginate the index, grouping it by category when asked
+fn components_list_response(liste: &[ComponentsList], query: &ListQuery) -> Response<Body> {
+    let (filtered, pagination) = list_page(liste, query);
+
+    if query.group_by.as_deref() != Some("category") {
+        let mut response = JsonResponse::<Vec<ComponentsList>>::make_success(
+            "found components successfuly",
+            filtered,
+        );
+        if let Some(pagination) = pagination {
+            response = response.with_pagination(pagination);
+        }
+        return json_response_builder(StatusCode::OK, response);
+    }
+
+    let mut groups: Vec<ComponentGroup> = Vec::new();
+    for component in filtered {
+        let category = component
+            .meta
+            .category
+            .clone()
+            .unwrap_or("Uncategorized".to_string());
+
+        match groups.iter_mut().find(|g| g.category == category) {
+            Some(group) => group.components.push(component),
+            None => groups.push(ComponentGroup {
+                category,
+                components: vec![component],
+            }),
+        }
+    }
+    groups.sort_by(|a, b| a.category.cmp(&b.category));
+
+    let mut response =
+        JsonResponse::<Vec<ComponentGroup>>::make_success("found components successfuly", groups);
+    if let Some(pagination) = pagination {
+        response = response.with_pagination(pagination);
+    }
+    json_response_builder(StatusCode::OK, response)
+}
+
+/// The components of the index matching the filters of the query, sorted, and
+/// the requested page of them when the query asks for one
+fn list_page(
+    liste: &[ComponentsList],
+    query: &ListQuery,
+) -> (Vec<ComponentsList>, Option<Pagination>) {
+    let status = query.status.as_deref().and_then(ComponentStatus::parse);
+    let prefix = query.q.as_ref().map(|q| q.to_lowercase());
+
+    let mut filtered: Vec<ComponentsList> = liste
+        .iter()
+        .filter(|c| status.is_none() || c.meta.status == status)
+        .filter(|c| match &query.owner {
+            Some(owner) => c.meta.owner.as_deref() == Some(owner.as_str()),
+            None => true,
+        })
+        .filter(|c| query.legacy.is_none_or(|legacy| c.is_legacy == legacy))
+        .filter(|c| match &query.category {
+            Some(category) => c.meta.category.as_deref() == Some(category.as_str()),
+            None => true,
+        })
+        .filter(|c| match &prefix {
+            Some(prefix) => c.name.to_lowercase().starts_with(prefix),
+            None => true,
+        })
+        .cloned()
+        .collect();
+
+    match query.sort.as_deref() {
+        // most recently updated first
+        Some("updated") => filtered.sort_by(|a, b| {
+            b.updated_at
+                .cmp(&a.updated_at)
+                .then_with(|| a.name.cmp(&b.name))
+        }),
+        Some("name") => filtered.sort_by_key(|c| c.name.to_lowercase()),
+        _ => {}
+    }
+
+    // without page parameters the whole list is returned as before
+    let pagination = if query.page.is_some() || query.per_page.is_some() {
+        let total = filtered.len();
+        let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
+        let page = query.page.unwrap_or(1).max(1);
+
+        filtered = filtered
+            .into_iter()
+            .skip(page.saturating_sub(1).saturating_mul(per_page))
+            .take(per_page)
+            .collect();
+
+        Some(Pagination {
+            total,
+            page,
+            per_page,
+            total_pages: total.div_ceil(per_page),
+        })
+    } else {
+        None
+    };
+
+    (filtered, pagination)
+}
+
+pub async fn search_components(
+    state: Extension<SharedState>,
+    Query(query): Query<SearchQuery>,
+) -> impl IntoResponse {
+    if query.q.chars().count() > MAX_QUERY_LEN {
+        return json_response_builder(
+            StatusCode::BAD_REQUEST,
+            JsonResponse::<String>::make_error(format!(
+                "the query is longer than {MAX_QUERY_LEN} characters"
+            )),
+        );
+    }
+
+    // the index is shared, the lock is not held while it is searched
+    let search_index = Arc::clone(&state.read().await.search_index);
+
+    if search_index.is_empty() {
+        return json_response_builder(
+            StatusCode::SERVICE_UNAVAILABLE,
+            JsonResponse::<String>::make_error(
+                "the search index is empty, list the components to sync the repository".to_string(),
+            ),
+        );
+    }
+
+    // scores every candidate token, the other requests move to the other workers
+    let hits = tokio::task::block_in_place(|| {
+        search_index.search(&query.q, query.limit.unwrap_or(20).clamp(1, 100))
+    });
+
+    json_response_builder(
+        StatusCode::OK,
+        JsonResponse::<Vec<SearchHit>>::make_success("search done", hits),
+    )
+}
+
+pub async fn get_component(
+    state: Extension<SharedState>,
+    slots: Extension<WorkSlots>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<ComponentQuery>,
+) -> impl IntoResponse {
+    let repo_config = match read_repo_config() {
         Ok(res) => res,
         Err(err) => {
             println!("Error : {}", err);
@@ -34,7 +457,7 @@ pub async fn list_components(state: Extension<SharedState>) -> impl IntoResponse
         }
     };
 
-    let (username, repo, branch) = match extract_repo_info(&config_content) {
+    let (username, repo, _branch) = match extract_repo_info(&repo_config) {
         Some(res) => res,
         None => {
             return json_response_builder(
@@ -44,108 +467,253 @@ pub async fn list_components(state: Extension<SharedState>) -> impl IntoResponse
         }
     };
 
-    let holding_folder = format!("/etc/compo-doc/tmp/{}/components", repo);
+    let (file_path, commit, props, examples) = {
+        let state = state.read().await;
+        let Some(file_path) = component_file_path(&state.comp_liste, repo, &id) else {
+            return AppError::NotFound.into_response();
+        };
+        (
+            file_path,
+            state.curr_ver.trim().to_string(),
+            state.props.get(&id).cloned(),
+            state.examples.get(&id).cloned(),
+        )
+    };
+    println!("{}", file_path);
+
+    // the same commit and options always give the same component
+    let inline_assets = query.inline_assets.unwrap_or(false);
+    let cache_key = CacheKey::new(
+        &commit,
+        "component",
+        &id,
+        &format!("inline_assets={inline_assets}"),
+    );
+    if let Some(response) = cache_key.as_ref().and_then(|key| lookup(key, &headers)) {
+        return response;
+    }
+
+    let mut component_infos = ComponentModel {
+        name: String::new(),
+        type_name: String::new(),
+        comp_code: String::new(),
+        comp_type: String::new(),
+        props: Vec::new(),
+        is_legacy: false,
+        legacy: None,
+        meta: ComponentMeta::default(),
+        styles: Vec::new(),
+        assets: Vec::new(),
+        source_map: None,
+        dependencies: Vec::new(),
+        examples: Vec::new(),
+        diagnostics: Vec::new(),
+    };
 
-    let fetched_version = match execute_commande(&format!(
-        "git ls-remote https://github.com/{}/{}.git {:?}",
-        &username, &repo, &branch
-    )) {
-        Ok(v) => v.trim().split("refs").next().unwrap().to_string(),
+    let code = match read_from_file_ut(&file_path) {
+        Ok(re) => re,
         Err(err) => {
-            print!("{}", err);
+            println!("{}", err);
             return json_response_builder(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                JsonResponse::<String>::make_error("Error while checking repo version".to_string()),
+                JsonResponse::<String>::make_error("could read file content".to_string()),
             );
         }
     };
-    println!("curr : {}", &state.curr_ver);
-    println!("fetched : {}", &fetched_version);
 
-    if fetched_version == state.curr_ver {
-        return json_response_builder(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            JsonResponse::<Vec<ComponentsList>>::make_success(
-                "found components successfuly",
-                state.comp_liste.clone(),
-            ),
-        );
+    if let Err(diagnostic) = check_file_size(&format!("components/{id}"), code.len()) {
+        return limit_response(diagnostic);
     }
 
-    let _ = match get_new_repo_ver(&repo, &branch, &username) {
-        Ok(r) => {
-            state.curr_ver = fetched_version;
-            r
+    let entry = index_component(&id, &code);
+    component_infos.is_legacy = entry.is_legacy;
+    component_infos.legacy = entry.legacy;
+    component_infos.meta = entry.meta;
+
+    // a component without a props type has no props
+    let type_name = find_used_type(&code).ok().flatten().unwrap_or_default();
+
+    // extracted and completed by the storybook argTypes during the sync
+    component_infos.props = props.unwrap_or_default();
+    component_infos.type_name = type_name.clone();
+    component_infos.comp_type = type_name;
+
+    // merged once per commit, a restart reads it back
+    let stored = if commit.is_empty() {
+        None
+    } else {
+        load_merged(repo, &commit, &id)
+    };
+
+    // held until the response is built, the styles are collected too
+    let _merge_slot = match stored {
+        Some(_) => None,
+        None => match slots.merge() {
+            Some(slot) => Some(slot),
+            None => return busy(),
+        },
+    };
+
+    let (cleared_imports, sources) = match stored {
+        Some(merged) => (merged.code, merged.sources),
+        None => {
+            // bounded by the merge limits, the other requests move to the other workers
+            let (merged, sources) =
+                match tokio::task::block_in_place(|| merge_with_sources(&code, repo)) {
+                    Ok(merged) => merged,
+                    Err(diagnostic) => return limit_response(diagnostic),
+                };
+            if !commit.is_empty()
+                && let Err(err) = save_merged(repo, &commit, &id, &merged, &sources)
+            {
+                println!("could not save the merged code of {id} : {err}");
+            }
+            (merged, sources)
         }
+    };
+
+    // link the merged blocks to the files at the synced commit when we know it
+    let source_root = if commit.is_empty() {
+        None
+    } else {
+        Some(format!(
+            "https://github.com/{username}/{repo}/blob/{commit}/"
+        ))
+    };
+
+    component_infos.source_map = match build_source_map(
+        &cleared_imports,
+        &sources,
+        &format!("components/{id}"),
+        &format!("/etc/compo-doc/tmp/{repo}"),
+        source_root.as_deref(),
+    ) {
+        Ok(map) => Some(map),
         Err(err) => {
-            println!("Error occured while cloning repo: {} ", err);
-            return json_response_builder(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                JsonResponse::<String>::make_error("Error while checking repo version".to_string()),
-            );
+            println!("could not build source map for {id} : {err}");
+            None
         }
     };
 
-    let files_liste = match list_dir_contents(&holding_folder) {
+    component_infos.comp_code = cleared_imports;
+
+    component_infos.examples = examples.unwrap_or_default();
+
+    component_infos.dependencies = component_npm_dependencies(
+        &format!("/etc/compo-doc/tmp/{repo}"),
+        &format!("components/{id}"),
+    );
+
+    let collected = collect_component_styles(&code, repo, inline_assets);
+    component_infos.styles = collected.styles;
+    component_infos.assets = collected.assets;
+    component_infos.diagnostics = collected.diagnostics;
+
+    let body = serde_json::to_string(&JsonResponse::<ComponentModel>::make_success(
+        "repository saved and reached",
+        component_infos,
+    ))
+    .unwrap();
+
+    respond(cache_key.as_ref(), "application/json", body)
+}
+
+pub async fn get_component_bundle(
+    state: Extension<SharedState>,
+    slots: Extension<WorkSlots>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<BundleQuery>,
+) -> impl IntoResponse {
+    let repo_config = match read_repo_config() {
         Ok(res) => res,
         Err(err) => {
             println!("Error : {}", err);
             return json_response_builder(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 JsonResponse::<String>::make_error(
-                    "could not read the components liste (either no repo found or no components)"
-                        .to_string(),
+                    "could not write the repository to file please try again later".to_string(),
                 ),
             );
         }
     };
 
-    let final_liste: Vec<ComponentsList> = files_liste
-        //iterate through the files liste
-        .iter()
-        .map(|file| -> ComponentsList {
-            // read the current file usinf it's path/name
-            let file_content = match read_from_file_ut(&format!("{holding_folder}/{file}")) {
-                Ok(res) => res,
-                Err(err) => {
-                    println!("{err}");
-                    return ComponentsList {
-                        name: file.to_string(),
-                        is_legacy: false,
-                    };
-                }
-            };
-
-            let mut is_legacy = false;
-
-            // checking for the presence of the legacy flag
-            if file_content.contains("//<legacy") {
-                is_legacy = true
-            }
+    let (_username, repo, _branch) = match extract_repo_info(&repo_config) {
+        Some(res) => res,
+        None => {
+            return json_response_builder(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse::<String>::make_error("could not parse repository url".to_string()),
+            );
+        }
+    };
 
-            // returning the list elements
-            return ComponentsList {
-                name: file.to_string(),
-                is_legacy,
-            };
-        })
-        // collecting the iterator into a vector (kind of Array)
-        .collect();
+    let (file_path, commit) = {
+        let state = state.read().await;
+        let Some(file_path) = component_file_path(&state.comp_liste, repo, &id) else {
+            return AppError::NotFound.into_response();
+        };
+        (file_path, state.curr_ver.clone())
+    };
 
-    state.comp_liste = final_liste.clone();
+    // a free cdn would let any caller fill the cache with its own bundles
+    let cdn_base = match query.cdn.as_deref().map(allowed_cdn_base) {
+        None => DEFAULT_CDN_BASE,
+        Some(Some(cdn_base)) => cdn_base,
+        Some(None) => {
+            return json_response_builder(
+                StatusCode::BAD_REQUEST,
+                JsonResponse::<String>::make_error(format!(
+                    "cdn must be one of {}",
+                    ALLOWED_CDN_BASES.join(", ")
+                )),
+            );
+        }
+    };
+    let cache_key = CacheKey::new(&commit, "bundle", &id, &format!("cdn={cdn_base}"));
+    if let Some(response) = cache_key.as_ref().and_then(|key| lookup(key, &headers)) {
+        return response;
+    }
 
-    // returnig the response
-    return json_response_builder(
-        StatusCode::INTERNAL_SERVER_ERROR,
-        JsonResponse::<Vec<ComponentsList>>::make_success(
-            "found components successfuly",
-            final_liste,
-        ),
-    );
+    let code = match read_from_file_ut(&file_path) {
+        Ok(re) => re,
+        Err(err) => {
+            println!("{}", err);
+            return json_response_builder(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse::<String>::make_error("could read file content".to_string()),
+            );
+        }
+    };
+
+    if let Err(diagnostic) = check_file_size(&format!("components/{id}"), code.len()) {
+        return limit_response(diagnostic);
+    }
+
+    let Some(_merge_slot) = slots.merge() else {
+        return busy();
+    };
+    let bundle = match tokio::task::block_in_place(|| bundle_component(&code, repo, cdn_base)) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("Error while bundling {id} : {err}");
+            return json_response_builder(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                JsonResponse::<String>::make_error(format!("could not bundle component : {err}")),
+            );
+        }
+    };
+
+    // served as a raw module so it can be imported straight from the browser
+    respond(cache_key.as_ref(), "text/javascript; charset=utf-8", bundle)
 }
 
-pub async fn get_component(Path(id): Path<String>) -> impl IntoResponse {
-    let config_content = match read_from_file_ut("/etc/compo-doc/config/config") {
+pub async fn get_component_usages(
+    state: Extension<SharedState>,
+    slots: Extension<WorkSlots>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let repo_config = match read_repo_config() {
         Ok(res) => res,
         Err(err) => {
             println!("Error : {}", err);
@@ -158,7 +726,7 @@ pub async fn get_component(Path(id): Path<String>) -> impl IntoResponse {
         }
     };
 
-    let (_username, repo, _branch) = match extract_repo_info(&config_content) {
+    let (_username, repo, _branch) = match extract_repo_info(&repo_config) {
         Some(res) => res,
         None => {
             return json_response_builder(
@@ -168,15 +736,8 @@ pub async fn get_component(Path(id): Path<String>) -> impl IntoResponse {
         }
     };
 
-    let file_path = format!("/etc/compo-doc/tmp/{repo}/components/{id}");
-    println!("{}", file_path);
-
-    let mut component_infos = ComponentModel {
-        name: String::new(),
-        type_name: String::new(),
-        comp_code: String::new(),
-        comp_type: String::new(),
-        is_legacy: false,
+    let Some(file_path) = component_file_path(&state.read().await.comp_liste, repo, &id) else {
+        return AppError::NotFound.into_response();
     };
 
     let code = match read_from_file_ut(&file_path) {
@@ -184,56 +745,191 @@ pub async fn get_component(Path(id): Path<String>) -> impl IntoResponse {
         Err(err) => {
             println!("{}", err);
             return json_response_builder(
-                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::NOT_FOUND,
                 JsonResponse::<String>::make_error("could read file content".to_string()),
             );
         }
     };
 
-    if code.contains("//<legacy") {
-        component_infos.is_legacy = true
-    }
+    let component_name =
+        principal_component_name(&code).unwrap_or(id.split('.').next().unwrap_or(&id).to_string());
+    let known_props: Vec<String> = extract_props(&code).into_iter().map(|p| p.name).collect();
 
-    let type_name = find_used_type(&code).unwrap().unwrap();
+    let Some(_report_slot) = slots.report() else {
+        return busy();
+    };
+    // walks and parses the whole clone, the other requests move to the other workers
+    let usages = tokio::task::block_in_place(|| {
+        component_usages(
+            &format!("/etc/compo-doc/tmp/{repo}"),
+            &format!("components/{id}"),
+            &component_name,
+            &known_props,
+        )
+    });
 
-    let mut extractor = TypeExtractor::new(&type_name);
-    let typing = extractor.extract_from_str(&code).unwrap();
+    json_response_builder(
+        StatusCode::OK,
+        JsonResponse::<ComponentUsages>::make_success("usages found", usages),
+    )
+}
 
-    component_infos.type_name = type_name.clone();
-    component_infos.comp_type = type_name;
+pub async fn get_dead_code_report(slots: Extension<WorkSlots>) -> impl IntoResponse {
+    let repo_config = match read_repo_config() {
+        Ok(res) => res,
+        Err(err) => {
+            println!("Error : {}", err);
+            return json_response_builder(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse::<String>::make_error(
+                    "could not write the repository to file please try again later".to_string(),
+                ),
+            );
+        }
+    };
 
-    let cleared_imports = merge_recurse(&code, &repo);
+    let (_username, repo, _branch) = match extract_repo_info(&repo_config) {
+        Some(res) => res,
+        None => {
+            return json_response_builder(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse::<String>::make_error("could not parse repository url".to_string()),
+            );
+        }
+    };
 
-    component_infos.comp_code = cleared_imports;
+    let Some(_report_slot) = slots.report() else {
+        return busy();
+    };
+    // reads and parses the whole clone, the other requests move to the other workers
+    let report = tokio::task::block_in_place(|| {
+        dead_code_report(&format!("/etc/compo-doc/tmp/{repo}"), "components")
+    });
 
-    return json_response_builder(
+    json_response_builder(
         StatusCode::OK,
-        JsonResponse::<ComponentModel>::make_success(
-            "repository saved and reached",
-            component_infos,
+        JsonResponse::<DeadCodeReport>::make_success("dead code report", report),
+    )
+}
+
+pub async fn compare_refs(
+    slots: Extension<WorkSlots>,
+    Query(query): Query<CompareQuery>,
+) -> impl IntoResponse {
+    let repo_config = match read_repo_config() {
+        Ok(res) => res,
+        Err(err) => {
+            println!("Error : {}", err);
+            return json_response_builder(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse::<String>::make_error(
+                    "could not write the repository to file please try again later".to_string(),
+                ),
+            );
+        }
+    };
+
+    let (_username, repo, _branch) = match extract_repo_info(&repo_config) {
+        Some(res) => res,
+        None => {
+            return json_response_builder(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse::<String>::make_error("could not parse repository url".to_string()),
+            );
+        }
+    };
+
+    let Some(_report_slot) = slots.report() else {
+        return busy();
+    };
+    // git and the parsing of both refs, the other requests move to the other workers
+    let diff = tokio::task::block_in_place(|| {
+        diff_refs(
+            &format!("/etc/compo-doc/tmp/{repo}"),
+            &query.from,
+            &query.to,
+            "components",
+        )
+    });
+
+    match diff {
+        Ok(report) => json_response_builder(
+            StatusCode::OK,
+            JsonResponse::<ApiDiffReport>::make_success("refs compared", report),
         ),
-    );
+        Err(err) => json_response_builder(
+            StatusCode::BAD_REQUEST,
+            JsonResponse::<String>::make_error(err),
+        ),
+    }
 }
 
-pub async fn setup_config(
+pub async fn get_component_history(
     state: Extension<SharedState>,
-    Json(config): Json<ConfigContent>,
+    slots: Extension<WorkSlots>,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
 ) -> impl IntoResponse {
-    let mut shared_state = state.write().await;
-    // delete old config
-    let _ = execute_commande("rm /etc/compo-doc/config/config");
-
-    // create repo save foramt
-    let repo_str = format!("{}/{}", &config.repo, &config.branch);
-    // write the repo to file for later use
-    match write_to_file_ut("/etc/compo-doc/config/config", &repo_str) {
-        // Do nothing if special if succede
+    let repo_config = match read_repo_config() {
         Ok(res) => res,
         Err(err) => {
-            // Print error to console
-            println!("{}", err);
+            println!("Error : {}", err);
+            return json_response_builder(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse::<String>::make_error(
+                    "could not write the repository to file please try again later".to_string(),
+                ),
+            );
+        }
+    };
 
-            // Return error to user
+    let (_username, repo, _branch) = match extract_repo_info(&repo_config) {
+        Some(res) => res,
+        None => {
+            return json_response_builder(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse::<String>::make_error("could not parse repository url".to_string()),
+            );
+        }
+    };
+
+    if component_file_path(&state.read().await.comp_liste, repo, &id).is_none() {
+        return AppError::NotFound.into_response();
+    }
+    let repo_dir = format!("/etc/compo-doc/tmp/{repo}");
+    let component_path = format!("components/{id}");
+
+    let per_page = query.per_page.unwrap_or(20).clamp(1, 100);
+    let page = query.page.unwrap_or(1).max(1);
+
+    let Some(_report_slot) = slots.report() else {
+        return busy();
+    };
+    // git and the props diffs, the other requests move to the other workers
+    let (total, entries) = tokio::task::block_in_place(|| {
+        component_history(&repo_dir, &component_path, page, per_page)
+    });
+
+    json_response_builder(
+        StatusCode::OK,
+        JsonResponse::<Vec<HistoryEntry>>::make_success("component history", entries)
+            .with_pagination(Pagination {
+                total,
+                page,
+                per_page,
+                total_pages: total.div_ceil(per_page),
+            }),
+    )
+}
+
+pub async fn get_changelog(
+    slots: Extension<WorkSlots>,
+    Query(query): Query<CompareQuery>,
+) -> impl IntoResponse {
+    let repo_config = match read_repo_config() {
+        Ok(res) => res,
+        Err(err) => {
+            println!("Error : {}", err);
             return json_response_builder(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 JsonResponse::<String>::make_error(
@@ -243,7 +939,7 @@ pub async fn setup_config(
         }
     };
 
-    let (username, repo, branch) = match extract_repo_info(&repo_str) {
+    let (_username, repo, _branch) = match extract_repo_info(&repo_config) {
         Some(res) => res,
         None => {
             return json_response_builder(
@@ -253,38 +949,210 @@ pub async fn setup_config(
         }
     };
 
-    let _ = match get_new_repo_ver(&config.repo, &branch, &username) {
-        Ok(r) => r,
+    let Some(_report_slot) = slots.report() else {
+        return busy();
+    };
+    let changelog = tokio::task::block_in_place(|| {
+        library_changelog(
+            &format!("/etc/compo-doc/tmp/{repo}"),
+            &query.from,
+            &query.to,
+            "components",
+        )
+    });
+
+    match changelog {
+        Ok(markdown) => Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "text/markdown; charset=utf-8")
+            .body(Body::from(markdown))
+            .unwrap(),
+        Err(err) => json_response_builder(
+            StatusCode::BAD_REQUEST,
+            JsonResponse::<String>::make_error(err),
+        ),
+    }
+}
+
+pub async fn setup_config(
+    state: Extension<SharedState>,
+    caller: Extension<Caller>,
+    slots: Extension<WorkSlots>,
+    Json(config): Json<ConfigContent>,
+) -> impl IntoResponse {
+    // every attempt to change the configuration is audited, dry runs excepted
+    let audit = |error: Option<String>| {
+        if config.dry_run {
+            return;
+        }
+        let mut event = AuditEvent::new("config_changed", &caller.name, error.is_none());
+        event.repo = Some(config.repo.chars().take(512).collect());
+        event.branch = Some(config.branch.chars().take(255).collect());
+        event.error = error;
+        record(event);
+    };
+
+    // nothing reaches the disk or git before being validated
+    let repo = match validate_config(&config, &AllowedHosts::load()) {
+        Ok(repo) => repo,
+        Err(errors) => {
+            audit(Some("invalid configuration".to_string()));
+            return json_response_builder(
+                StatusCode::BAD_REQUEST,
+                JsonResponse::<String>::make_error("invalid configuration".to_string())
+                    .with_errors(errors),
+            );
+        }
+    };
+
+    // check the repository and the branch can be reached before saving anything
+    let fetch_version = match git_output(
+        "/etc/compo-doc/tmp",
+        &[
+            "ls-remote",
+            "--exit-code",
+            &repo.url,
+            &format!("refs/heads/{}", repo.branch),
+        ],
+    ) {
+        Ok(v) => v.trim().split("refs").next().unwrap().trim().to_string(),
+        Err(err) => {
+            println!("{}", err);
+            // ls-remote answers an empty list for an unknown branch
+            let error =
+                if git_output("/etc/compo-doc/tmp", &["ls-remote", "--heads", &repo.url]).is_ok() {
+                    FieldError {
+                        field: "branch".to_string(),
+                        message: format!("branch {} not found", repo.branch),
+                    }
+                } else {
+                    FieldError {
+                        field: "repo".to_string(),
+                        message: "repository not reachable".to_string(),
+                    }
+                };
+            audit(Some(error.message.clone()));
+            return json_response_builder(
+                StatusCode::BAD_REQUEST,
+                JsonResponse::<String>::make_error("invalid configuration".to_string())
+                    .with_errors(vec![error]),
+            );
+        }
+    };
+
+    if config.dry_run {
+        return json_response_builder(
+            StatusCode::OK,
+            JsonResponse::<String>::make_success(
+                "configuration is valid and the repository is reachable",
+                fetch_version.trim().to_string(),
+            ),
+        );
+    }
+
+    // configuring a repository indexes it, nothing changes while another sync runs
+    let Some(_sync_slot) = slots.sync() else {
+        return busy();
+    };
+
+    // delete old config
+    let _ = std::fs::remove_file("/etc/compo-doc/config/config");
+
+    // the url and the branch apart, a branch may contain /
+    let repo_str = serde_json::to_string(&RepoConfig {
+        url: repo.url.clone(),
+        branch: repo.branch.clone(),
+    })
+    .unwrap();
+    // write the repo to file for later use
+    match write_to_file_ut("/etc/compo-doc/config/config", &repo_str) {
+        // Do nothing if special if succede
+        Ok(res) => res,
         Err(err) => {
-            println!("Error occured while cloning repo: {} ", err);
+            // Print error to console
+            println!("{}", err);
+            audit(Some(err));
+
+            // Return error to user
             return json_response_builder(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                JsonResponse::<String>::make_error("Error while checking repo version".to_string()),
+                JsonResponse::<String>::make_error(
+                    "could not write the repository to file please try again later".to_string(),
+                ),
             );
         }
     };
 
-    let fetch_version =
-        match execute_commande(&format!("git ls-remote {} {:?}", &config.repo, &branch)) {
-            Ok(v) => v.trim().split("refs").next().unwrap().to_string(),
-            Err(err) => {
-                print!("{}", err);
-                return json_response_builder(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    JsonResponse::<String>::make_error(
-                        "Error while checking repo version".to_string(),
-                    ),
-                );
-            }
-        };
+    // the previous repository is no longer served, the sync indexes the new one
+    clear_index(&mut *state.write().await);
+    clear_component_cache();
 
-    shared_state.curr_ver = fetch_version;
+    if let Err((status, message)) = audited_sync(&state, &caller.name, true).await {
+        println!("Error occured while syncing the repository: {message}");
+        audit(Some(message.clone()));
+        return json_response_builder(status, JsonResponse::<String>::make_error(message));
+    }
+    println!("repository {} configured and indexed", repo.name);
+    audit(None);
 
     // Return success to user
-    return json_response_builder(
+    json_response_builder(
         StatusCode::OK,
         JsonResponse::<String>::make_success("repository saved and reached", "OK".to_string()),
-    );
+    )
+}
+
+pub async fn get_audit_log(Query(query): Query<AuditQuery>) -> impl IntoResponse {
+    let per_page = query.per_page.unwrap_or(100).clamp(1, 1000);
+    let page = query.page.unwrap_or(1).max(1);
+
+    let (total, events) = read_events(&query, page, per_page);
+
+    json_response_builder(
+        StatusCode::OK,
+        JsonResponse::<Vec<AuditEvent>>::make_success("audit events", events).with_pagination(
+            Pagination {
+                total,
+                page,
+                per_page,
+                total_pages: total.div_ceil(per_page),
+            },
+        ),
+    )
+}
+
+pub async fn get_allowed_hosts() -> impl IntoResponse {
+    json_response_builder(
+        StatusCode::OK,
+        JsonResponse::<AllowedHosts>::make_success("allowed hosts", AllowedHosts::load()),
+    )
+}
+
+pub async fn set_allowed_hosts(Json(hosts): Json<AllowedHosts>) -> impl IntoResponse {
+    let errors = hosts.validate();
+    if !errors.is_empty() {
+        return json_response_builder(
+            StatusCode::BAD_REQUEST,
+            JsonResponse::<String>::make_error("invalid hosts".to_string()).with_errors(errors),
+        );
+    }
+
+    let hosts = AllowedHosts {
+        hosts: hosts.hosts.iter().map(|h| h.to_lowercase()).collect(),
+    };
+    match hosts.save() {
+        Ok(()) => json_response_builder(
+            StatusCode::OK,
+            JsonResponse::<AllowedHosts>::make_success("allowed hosts saved", hosts),
+        ),
+        Err(err) => {
+            println!("{}", err);
+            json_response_builder(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse::<String>::make_error("could not save the allowed hosts".to_string()),
+            )
+        }
+    }
 }
 
 // let fetch_version = match execute_commande(&format!(
@@ -297,3 +1165,123 @@ pub async fn setup_config(
 //             return;
 //         }
 //     };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::utils::indexer::index_component;
+
+    fn liste() -> Vec<ComponentsList> {
+        [
+            (
+                "Button.tsx",
+                "//<meta category=\"Forms\" status=\"stable\" owner=\"ui\">\n",
+                30,
+            ),
+            (
+                "badge.tsx",
+                "//<meta category=\"Display\" status=\"experimental\">\n",
+                10,
+            ),
+            ("Card.tsx", "//<legacy>\n", 20),
+            (
+                "Input.tsx",
+                "//<meta category=\"Forms\" owner=\"forms\">\n",
+                40,
+            ),
+        ]
+        .into_iter()
+        .map(|(name, code, updated_at)| {
+            let mut entry = index_component(name, code);
+            entry.updated_at = Some(updated_at);
+            entry
+        })
+        .collect()
+    }
+
+    fn names(query: ListQuery) -> Vec<String> {
+        list_page(&liste(), &query)
+            .0
+            .into_iter()
+            .map(|c| c.name)
+            .collect()
+    }
+
+    #[test]
+    fn filters_the_components() {
+        let query = |f: fn(&mut ListQuery)| {
+            let mut query = ListQuery::default();
+            f(&mut query);
+            names(query)
+        };
+
+        assert_eq!(
+            query(|q| q.category = Some("Forms".to_string())),
+            ["Button.tsx", "Input.tsx"]
+        );
+        assert_eq!(
+            query(|q| q.status = Some("Stable".to_string())),
+            ["Button.tsx"]
+        );
+        assert_eq!(
+            query(|q| q.owner = Some("forms".to_string())),
+            ["Input.tsx"]
+        );
+        assert_eq!(query(|q| q.legacy = Some(true)), ["Card.tsx"]);
+        assert_eq!(
+            query(|q| q.q = Some("B".to_string())),
+            ["Button.tsx", "badge.tsx"]
+        );
+        // an unknown status filters nothing out
+        assert_eq!(query(|q| q.status = Some("old".to_string())).len(), 4);
+    }
+
+    #[test]
+    fn sorts_the_components() {
+        let by = |sort: &str| {
+            names(ListQuery {
+                sort: Some(sort.to_string()),
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            by("name"),
+            ["badge.tsx", "Button.tsx", "Card.tsx", "Input.tsx"]
+        );
+        assert_eq!(
+            by("updated"),
+            ["Input.tsx", "Button.tsx", "Card.tsx", "badge.tsx"]
+        );
+    }
+
+    #[test]
+    fn pages_the_components() {
+        let page = |page, per_page| {
+            list_page(
+                &liste(),
+                &ListQuery {
+                    sort: Some("name".to_string()),
+                    page,
+                    per_page,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let (components, pagination) = page(Some(2), Some(3));
+        assert_eq!(components.len(), 1);
+        let pagination = pagination.unwrap();
+        assert_eq!(
+            (pagination.total, pagination.page, pagination.total_pages),
+            (4, 2, 2)
+        );
+
+        // page 0 is the first one, the pages past the end are empty
+        assert_eq!(page(Some(0), Some(3)).0[0].name, "badge.tsx");
+        assert!(page(Some(usize::MAX), Some(500)).0.is_empty());
+        // without page parameters the whole list comes back
+        assert!(page(None, None).1.is_none());
+    }
+}
@@ -0,0 +1,315 @@
+use std::{collections::HashSet, path::Path};
+
+use crate::utils::{
+    api_diff::{diff_props, diff_refs},
+    code_merge::{component_rel_path, get_imported_components},
+    search::extract_props,
+    structs::{ApiChange, HistoryEntry, SemverLevel},
+    utils::{git_output, read_repo_file},
+};
+
+/// One commit read from `git log`
+pub struct GitCommit {
+    pub sha: String,
+    pub author: String,
+    pub timestamp: i64,
+    // YYYY-MM-DD
+    pub date: String,
+    pub subject: String,
+    pub files: Vec<String>,
+}
+
+/// Commits touching `paths`, newest first. `range` limits the log
+/// (`v1.0.0..v2.0.0`), the refs in it must already be validated.
+pub fn git_log(repo_dir: &str, range: Option<&str>, paths: &[String]) -> Vec<GitCommit> {
+    let mut args = vec![
+        "log",
+        "--format=%x00%H%x1f%an%x1f%ct%x1f%as%x1f%s",
+        "--name-only",
+    ];
+    if let Some(range) = range {
+        args.push(range);
+    }
+    args.push("--");
+    args.extend(paths.iter().map(String::as_str));
+
+    let log = match git_output(repo_dir, &args) {
+        Ok(log) => log,
+        Err(err) => {
+            println!("could not read the git history : {err}");
+            return Vec::new();
+        }
+    };
+
+    let mut commits: Vec<GitCommit> = Vec::new();
+    for line in log.lines() {
+        if let Some(header) = line.strip_prefix('\0') {
+            let fields: Vec<&str> = header.splitn(5, '\x1f').collect();
+            if let [sha, author, timestamp, date, subject] = fields[..] {
+                commits.push(GitCommit {
+                    sha: sha.to_string(),
+                    author: author.to_string(),
+                    timestamp: timestamp.parse().unwrap_or_default(),
+                    date: date.to_string(),
+                    subject: subject.to_string(),
+                    files: Vec::new(),
+                });
+            }
+            continue;
+        }
+        if let Some(commit) = commits.last_mut()
+            && !line.trim().is_empty()
+        {
+            commit.files.push(line.trim().to_string());
+        }
+    }
+
+    commits
+}
+
+/// Components merged into `component_path`, followed through their own imports
+pub fn merged_dependencies(repo_dir: &str, component_path: &str) -> Vec<String> {
+    let mut seen: HashSet<String> = HashSet::from([component_path.to_string()]);
+    let mut pending = vec![component_path.to_string()];
+
+    while let Some(path) = pending.pop() {
+        let Ok(code) = read_repo_file(repo_dir, &path) else {
+            continue;
+        };
+        for component in get_imported_components(&code) {
+            let dependency = component_rel_path(&component);
+            if Path::new(&format!("{repo_dir}/{dependency}")).exists()
+                && seen.insert(dependency.clone())
+            {
+                pending.push(dependency);
+            }
+        }
+    }
+
+    seen.remove(component_path);
+    let mut dependencies: Vec<String> = seen.into_iter().collect();
+    dependencies.sort();
+    dependencies
+}
+
+/// Props changes brought by a commit to a component, empty when the file did
+/// not exist before it
+fn props_changes_at(repo_dir: &str, commit: &GitCommit, component_path: &str) -> Vec<ApiChange> {
+    if !commit.files.iter().any(|f| f == component_path) {
+        return Vec::new();
+    }
+
+    let show = |rev: String| git_output(repo_dir, &["show", &format!("{rev}:{component_path}")]);
+    let (Ok(before), Ok(after)) = (show(format!("{}^", commit.sha)), show(commit.sha.clone()))
+    else {
+        return Vec::new();
+    };
+
+    diff_props(&extract_props(&before), &extract_props(&after))
+}
+
+/// History of a component and of the components merged into it, newest first.
+/// Returns the total number of commits and the requested page.
+pub fn component_history(
+    repo_dir: &str,
+    component_path: &str,
+    page: usize,
+    per_page: usize,
+) -> (usize, Vec<HistoryEntry>) {
+    let mut paths = vec![component_path.to_string()];
+    paths.extend(merged_dependencies(repo_dir, component_path));
+
+    let commits = git_log(repo_dir, None, &paths);
+    let total = commits.len();
+
+    // the props are only diffed for the commits of the page
+    let entries = commits
+        .into_iter()
+        .skip(page.saturating_sub(1).saturating_mul(per_page))
+        .take(per_page)
+        .map(|commit| {
+            let props_changes = props_changes_at(repo_dir, &commit, component_path);
+            let level = props_changes
+                .iter()
+                .map(|c| c.level)
+                .max()
+                .unwrap_or(SemverLevel::Patch);
+
+            HistoryEntry {
+                via_dependency: !commit.files.iter().any(|f| f == component_path),
+                files: commit
+                    .files
+                    .into_iter()
+                    .filter(|f| paths.contains(f))
+                    .collect(),
+                commit: commit.sha,
+                author: commit.author,
+                timestamp: commit.timestamp,
+                subject: commit.subject,
+                level,
+                props_changes,
+            }
+        })
+        .collect();
+
+    (total, entries)
+}
+
+/// Markdown changelog of the whole library between two refs : the props API
+/// changes of every component followed by the commits touching it
+pub fn library_changelog(
+    repo_dir: &str,
+    from: &str,
+    to: &str,
+    component_dir: &str,
+) -> Result<String, String> {
+    let report = diff_refs(repo_dir, from, to, component_dir)?;
+    let commits = git_log(
+        repo_dir,
+        Some(&format!("{}..{}", report.from_commit, report.to_commit)),
+        &[component_dir.trim_matches('/').to_string()],
+    );
+
+    let mut markdown = format!("# Changelog {from}...{to}\n\n");
+    markdown.push_str(&format!(
+        "Release level : **{}**\n",
+        level_label(report.level)
+    ));
+
+    if report.components.is_empty() {
+        markdown.push_str("\nNo component changed.\n");
+        return Ok(markdown);
+    }
+
+    for component in &report.components {
+        markdown.push_str(&format!(
+            "\n## {} ({})\n\n",
+            component.component,
+            level_label(component.level)
+        ));
+
+        for change in &component.changes {
+            let marker = if change.level == SemverLevel::Major {
+                "**BREAKING** "
+            } else {
+                ""
+            };
+            markdown.push_str(&format!("- {marker}{}\n", change.detail));
+        }
+
+        let component_commits: Vec<&GitCommit> = commits
+            .iter()
+            .filter(|c| c.files.iter().any(|f| f == &component.file))
+            .collect();
+        if !component_commits.is_empty() {
+            markdown.push_str("\nCommits :\n\n");
+            for commit in component_commits {
+                markdown.push_str(&format!(
+                    "- {} ({}, {}, {})\n",
+                    commit.subject,
+                    &commit.sha[..7.min(commit.sha.len())],
+                    commit.author,
+                    commit.date
+                ));
+            }
+        }
+    }
+
+    Ok(markdown)
+}
+
+fn level_label(level: SemverLevel) -> &'static str {
+    match level {
+        SemverLevel::Major => "major",
+        SemverLevel::Minor => "minor",
+        SemverLevel::Patch => "patch",
+        SemverLevel::None => "none",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(repo_dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Dev", "-c", "user.email=dev@example.com"])
+            .args(args)
+            .current_dir(repo_dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?}");
+    }
+
+    fn commit(repo_dir: &Path, path: &str, code: &str, subject: &str) {
+        std::fs::write(repo_dir.join(path), code).unwrap();
+        git(repo_dir, &["add", "-A"]);
+        git(repo_dir, &["commit", "-q", "-m", subject]);
+    }
+
+    // Button is edited twice, then its Icon dependency once
+    fn history_repo(name: &str) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("compo-doc-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("components")).unwrap();
+        git(&root, &["init", "-q"]);
+
+        let button = "import Icon from './Icon';\ninterface ButtonProps { label: string }\nexport const Button = ({ label }: ButtonProps) => <Icon />;\n";
+        commit(
+            &root,
+            "components/Icon.tsx",
+            "export const Icon = () => <i />;\n",
+            "add icon",
+        );
+        commit(&root, "components/Button.tsx", button, "add button");
+        git(&root, &["tag", "v1"]);
+        commit(
+            &root,
+            "components/Button.tsx",
+            &button.replace("label: string", "label: string; size?: number"),
+            "add the size",
+        );
+        commit(
+            &root,
+            "components/Icon.tsx",
+            "export const Icon = () => <b />;\n",
+            "bold icon",
+        );
+        git(&root, &["tag", "v2"]);
+        root
+    }
+
+    #[test]
+    fn pages_the_history_of_a_component_and_its_dependencies() {
+        let root = history_repo("history");
+        let repo_dir = root.to_str().unwrap();
+
+        let (total, entries) = component_history(repo_dir, "components/Button.tsx", 1, 2);
+        assert_eq!(total, 4);
+        let subjects: Vec<&str> = entries.iter().map(|e| e.subject.as_str()).collect();
+        assert_eq!(subjects, ["bold icon", "add the size"]);
+        assert!(entries[0].via_dependency);
+        assert_eq!(entries[1].level, SemverLevel::Minor);
+
+        // page 0 is the first one, the pages past the end are empty
+        let (_, entries) = component_history(repo_dir, "components/Button.tsx", 0, 100);
+        assert_eq!(entries.len(), 4);
+        let (_, entries) = component_history(repo_dir, "components/Button.tsx", usize::MAX, 100);
+        assert!(entries.is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn writes_the_changelog_between_two_refs() {
+        let root = history_repo("changelog");
+
+        let markdown = library_changelog(root.to_str().unwrap(), "v1", "v2", "components").unwrap();
+        assert!(markdown.starts_with("# Changelog v1...v2\n\nRelease level : **minor**\n"));
+        assert!(markdown.contains("## Button (minor)"));
+        assert!(markdown.contains("- add the size ("));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+use swc_common::{GLOBALS, Globals, Mark, SyntaxContext};
+use swc_ecma_ast::{
+    BreakStmt, ContinueStmt, Ident, LabeledStmt, ObjectPatProp, Program, Prop, TsEnumMember,
+    TsGetterSignature, TsMethodSignature, TsPropertySignature, TsSetterSignature,
+};
+use swc_ecma_transforms_base::resolver;
+use swc_ecma_visit::{Visit, VisitWith};
+
+use crate::utils::tsx_parser::ParsedFile;
+
+/// One local name bound by an import statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportBinding {
+    pub spec: String,
+    // "default", "*" or the exported name
+    pub imported: String,
+    pub local: String,
+    pub type_only: bool,
+}
+
+/// Imports of one or several files, deduplicated and grouped by module
+#[derive(Debug, Default, Clone)]
+pub struct MergedImports {
+    pub bindings: Vec<ImportBinding>,
+    pub side_effects: Vec<String>,
+}
+
+impl MergedImports {
+    /// Add a binding, returns false when the same one is already there
+    pub fn add(&mut self, binding: ImportBinding) -> bool {
+        if self.bindings.contains(&binding) {
+            return false;
+        }
+        // a value import covers the type-only one
+        if let Some(existing) = self.bindings.iter_mut().find(|b| {
+            b.spec == binding.spec && b.imported == binding.imported && b.local == binding.local
+        }) {
+            existing.type_only &= binding.type_only;
+            return false;
+        }
+        self.bindings.push(binding);
+        true
+    }
+
+    pub fn add_side_effect(&mut self, spec: &str) {
+        if !self.side_effects.iter().any(|s| s == spec) {
+            self.side_effects.push(spec.to_string());
+        }
+    }
+
+    /// The binding currently owning a local name
+    pub fn bound_to(&self, local: &str) -> Option<&ImportBinding> {
+        self.bindings.iter().find(|b| b.local == local)
+    }
+
+    pub fn locals(&self) -> HashSet<String> {
+        self.bindings.iter().map(|b| b.local.clone()).collect()
+    }
+
+    /// Emit one statement per module (plus the extra default / namespace ones)
+    pub fn render(&self) -> String {
+        let mut specs: Vec<&str> = Vec::new();
+        for b in &self.bindings {
+            if !specs.contains(&b.spec.as_str()) {
+                specs.push(&b.spec);
+            }
+        }
+
+        let mut out = String::new();
+
+        for spec in &specs {
+            let of_spec: Vec<&ImportBinding> =
+                self.bindings.iter().filter(|b| &b.spec == spec).collect();
+
+            let named: Vec<&&ImportBinding> = of_spec
+                .iter()
+                .filter(|b| b.imported != "default" && b.imported != "*")
+                .collect();
+            let defaults: Vec<&&ImportBinding> =
+                of_spec.iter().filter(|b| b.imported == "default").collect();
+            let namespaces: Vec<&&ImportBinding> =
+                of_spec.iter().filter(|b| b.imported == "*").collect();
+
+            // the first value default shares the statement with the named list
+            let main_default = defaults.iter().find(|b| !b.type_only).copied();
+
+            if main_default.is_some() || !named.is_empty() {
+                let all_types = main_default.is_none() && named.iter().all(|b| b.type_only);
+
+                let entries: Vec<String> = named
+                    .iter()
+                    .map(|b| {
+                        let type_prefix = if b.type_only && !all_types {
+                            "type "
+                        } else {
+                            ""
+                        };
+                        if b.imported == b.local {
+                            format!("{type_prefix}{}", b.local)
+                        } else {
+                            format!("{type_prefix}{} as {}", b.imported, b.local)
+                        }
+                    })
+                    .collect();
+
+                let mut clause = Vec::new();
+                if let Some(d) = main_default {
+                    clause.push(d.local.clone());
+                }
+                if !entries.is_empty() {
+                    clause.push(format!("{{ {} }}", entries.join(", ")));
+                }
+
+                let keyword = if all_types { "import type" } else { "import" };
+                out.push_str(&format!(
+                    "{keyword} {} from \"{spec}\";\n",
+                    clause.join(", ")
+                ));
+            }
+
+            for d in defaults {
+                if main_default.is_some_and(|m| m.local == d.local) {
+                    continue;
+                }
+                let keyword = if d.type_only { "import type" } else { "import" };
+                out.push_str(&format!("{keyword} {} from \"{spec}\";\n", d.local));
+            }
+
+            for ns in namespaces {
+                let keyword = if ns.type_only {
+                    "import type"
+                } else {
+                    "import"
+                };
+                out.push_str(&format!("{keyword} * as {} from \"{spec}\";\n", ns.local));
+            }
+        }
+
+        // side effects already covered by a binding import are redundant
+        for spec in &self.side_effects {
+            if !specs.contains(&spec.as_str()) {
+                out.push_str(&format!("import \"{spec}\";\n"));
+            }
+        }
+
+        out
+    }
+}
+
+/// Pull every import statement out of a file, returns the parsed imports and
+/// the file without them
+pub fn take_imports(code: &str) -> (MergedImports, String) {
+    let stmt_re = Regex::new(
+        r#"(?m)^[ \t]*import\s+(?:(type)\s+)?(?:([^;'"]*?)\s+from\s+)?["']([^"']+)["'][ \t]*;?[ \t]*(?:\r?\n)?"#,
+    )
+    .unwrap();
+
+    let mut imports = MergedImports::default();
+
+    for cap in stmt_re.captures_iter(code) {
+        let statement_type_only = cap.get(1).is_some();
+        let spec = &cap[3];
+
+        match cap.get(2) {
+            Some(clause) => {
+                for binding in parse_import_clause(clause.as_str(), spec, statement_type_only) {
+                    imports.add(binding);
+                }
+            }
+            None => imports.add_side_effect(spec),
+        }
+    }
+
+    let body = stmt_re.replace_all(code, "").to_string();
+
+    (imports, body)
+}
+
+/// `Default, { a, b as c, type D }` | `* as ns` | `Default, * as ns`
+pub fn parse_import_clause(clause: &str, spec: &str, type_only: bool) -> Vec<ImportBinding> {
+    let mut bindings = Vec::new();
+    let binding = |imported: &str, local: &str, type_only: bool| ImportBinding {
+        spec: spec.to_string(),
+        imported: imported.to_string(),
+        local: local.to_string(),
+        type_only,
+    };
+
+    let (outside, named) = match (clause.find('{'), clause.rfind('}')) {
+        (Some(start), Some(end)) if start < end => (
+            format!("{}{}", &clause[..start], &clause[end + 1..]),
+            Some(&clause[start + 1..end]),
+        ),
+        _ => (clause.to_string(), None),
+    };
+
+    for part in outside.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.strip_prefix('*') {
+            Some(ns) => {
+                let local = ns.trim().trim_start_matches("as").trim();
+                bindings.push(binding("*", local, type_only));
+            }
+            None => bindings.push(binding("default", part, type_only)),
+        }
+    }
+
+    if let Some(named) = named {
+        for entry in named.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (entry_type_only, entry) = match entry.strip_prefix("type ") {
+                Some(rest) => (true, rest.trim()),
+                None => (type_only, entry),
+            };
+            let (imported, local) = match entry.split_once(" as ") {
+                Some((imported, local)) => (imported.trim(), local.trim()),
+                None => (entry, entry),
+            };
+            bindings.push(binding(imported, local, entry_type_only));
+        }
+    }
+
+    bindings
+}
+
+/// Names declared at the top level of a file (exports already stripped, so
+/// the declarations start at the beginning of the line)
+pub fn top_level_names(code: &str) -> Vec<String> {
+    let decl_re = Regex::new(
+        r#"(?m)^(?:declare\s+)?(?:async\s+)?(?:abstract\s+)?(?:function\*?|const|let|var|class|type|interface|enum)\s+([A-Za-z_$][A-Za-z0-9_$]*)"#,
+    )
+    .unwrap();
+
+    let mut names = Vec::new();
+    for cap in decl_re.captures_iter(code) {
+        let name = cap[1].to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Rename the top-level bindings of a file (its declarations and the names
+/// its removed imports bound). The references are resolved on the swc AST so
+/// the shadowing locals, property names, JSX attributes, object keys and
+/// strings are left alone. The code is patched in place to keep its lines.
+pub fn rename_identifiers(code: &str, renames: &HashMap<String, String>) -> String {
+    if renames.is_empty() {
+        return code.to_string();
+    }
+
+    let parsed = match ParsedFile::parse(code) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            println!("could not rename the merged identifiers : {err}");
+            return code.to_string();
+        }
+    };
+
+    let mut edits = GLOBALS.set(&Globals::new(), || {
+        let unresolved_mark = Mark::new();
+        let top_level_mark = Mark::new();
+        let program =
+            Program::Module(parsed.module).apply(resolver(unresolved_mark, top_level_mark, true));
+
+        let mut collector = RenameCollector {
+            renames,
+            // the removed imports are unresolved, the declarations top-level
+            contexts: [
+                SyntaxContext::empty().apply_mark(unresolved_mark),
+                SyntaxContext::empty().apply_mark(top_level_mark),
+            ],
+            edits: Vec::new(),
+        };
+        program.visit_with(&mut collector);
+        collector.edits
+    });
+
+    let start = parsed.cm.files().first().map_or(0, |file| file.start_pos.0);
+    edits.sort_by_key(|(lo, _, _)| std::cmp::Reverse(*lo));
+    edits.dedup_by_key(|(lo, _, _)| *lo);
+
+    let mut out = code.to_string();
+    for (lo, hi, replacement) in edits {
+        let (lo, hi) = ((lo - start) as usize, (hi - start) as usize);
+        if hi <= out.len() && out.is_char_boundary(lo) && out.is_char_boundary(hi) {
+            out.replace_range(lo..hi, &replacement);
+        }
+    }
+    out
+}
+
+/// Spans of the identifiers bound at the top level, with their new text
+struct RenameCollector<'a> {
+    renames: &'a HashMap<String, String>,
+    contexts: [SyntaxContext; 2],
+    edits: Vec<(u32, u32, String)>,
+}
+
+impl RenameCollector<'_> {
+    fn renamed(&self, ident: &Ident) -> Option<&String> {
+        if !self.contexts.contains(&ident.ctxt) {
+            return None;
+        }
+        self.renames.get(ident.sym.as_str())
+    }
+
+    fn edit(&mut self, ident: &Ident, replacement: String) {
+        self.edits
+            .push((ident.span.lo.0, ident.span.hi.0, replacement));
+    }
+}
+
+impl Visit for RenameCollector<'_> {
+    fn visit_ident(&mut self, ident: &Ident) {
+        if let Some(renamed) = self.renamed(ident) {
+            self.edit(ident, renamed.clone());
+        }
+    }
+
+    // `{ size }` keeps its key : `{ size: size_Child }`
+    fn visit_prop(&mut self, prop: &Prop) {
+        match prop {
+            Prop::Shorthand(ident) => {
+                if let Some(renamed) = self.renamed(ident) {
+                    self.edit(ident, format!("{}: {renamed}", ident.sym));
+                }
+            }
+            _ => prop.visit_children_with(self),
+        }
+    }
+
+    // `const { size } = props` binds `size_Child` from the `size` key
+    fn visit_object_pat_prop(&mut self, prop: &ObjectPatProp) {
+        match prop {
+            ObjectPatProp::Assign(assign) => {
+                if let Some(renamed) = self.renamed(&assign.key.id) {
+                    self.edit(&assign.key.id, format!("{}: {renamed}", assign.key.id.sym));
+                }
+                assign.key.type_ann.visit_with(self);
+                assign.value.visit_with(self);
+            }
+            _ => prop.visit_children_with(self),
+        }
+    }
+
+    // the member names of a type are keys, not references
+    fn visit_ts_property_signature(&mut self, n: &TsPropertySignature) {
+        if n.computed {
+            n.key.visit_with(self);
+        }
+        n.type_ann.visit_with(self);
+    }
+
+    fn visit_ts_method_signature(&mut self, n: &TsMethodSignature) {
+        if n.computed {
+            n.key.visit_with(self);
+        }
+        n.params.visit_with(self);
+        n.type_ann.visit_with(self);
+        n.type_params.visit_with(self);
+    }
+
+    fn visit_ts_getter_signature(&mut self, n: &TsGetterSignature) {
+        if n.computed {
+            n.key.visit_with(self);
+        }
+        n.type_ann.visit_with(self);
+    }
+
+    fn visit_ts_setter_signature(&mut self, n: &TsSetterSignature) {
+        if n.computed {
+            n.key.visit_with(self);
+        }
+        n.param.visit_with(self);
+    }
+
+    fn visit_ts_enum_member(&mut self, n: &TsEnumMember) {
+        n.init.visit_with(self);
+    }
+
+    // labels live in their own namespace
+    fn visit_labeled_stmt(&mut self, n: &LabeledStmt) {
+        n.body.visit_with(self);
+    }
+
+    fn visit_break_stmt(&mut self, _: &BreakStmt) {}
+
+    fn visit_continue_stmt(&mut self, _: &ContinueStmt) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renames(names: &[&str]) -> HashMap<String, String> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), format!("{name}_Child")))
+            .collect()
+    }
+
+    #[test]
+    fn renames_the_references_of_a_top_level_binding() {
+        let code = "const size = 1;\nexport const Child = () => <Icon size={size} />;\n";
+        assert_eq!(
+            rename_identifiers(code, &renames(&["size"])),
+            "const size_Child = 1;\nexport const Child = () => <Icon size={size_Child} />;\n"
+        );
+    }
+
+    #[test]
+    fn leaves_keys_members_and_strings_alone() {
+        let code = "const size = 1;\nconst a = { size: 1, b: \"size\" };\nconst c = obj.size;\n";
+        assert_eq!(
+            rename_identifiers(code, &renames(&["size"])),
+            "const size_Child = 1;\nconst a = { size: 1, b: \"size\" };\nconst c = obj.size;\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_key_of_a_shorthand_property() {
+        let code = "const size = 1;\nconst a = { size };\nconst { size: s, size: t } = a;\n";
+        assert_eq!(
+            rename_identifiers(code, &renames(&["size"])),
+            "const size_Child = 1;\nconst a = { size: size_Child };\nconst { size: s, size: t } = a;\n"
+        );
+    }
+
+    #[test]
+    fn leaves_shadowing_locals_alone() {
+        let code = "const size = 1;\nfunction f(size) { return size; }\n";
+        assert_eq!(
+            rename_identifiers(code, &renames(&["size"])),
+            "const size_Child = 1;\nfunction f(size) { return size; }\n"
+        );
+    }
+
+    #[test]
+    fn renames_type_references_but_not_member_names() {
+        let code = "type Size = number;\ninterface Props { Size: Size }\n";
+        assert_eq!(
+            rename_identifiers(code, &renames(&["Size"])),
+            "type Size_Child = number;\ninterface Props { Size: Size_Child }\n"
+        );
+    }
+
+    #[test]
+    fn renames_the_locals_of_the_removed_imports() {
+        let code = "export const Child = () => <div className={styles.root} />;\n";
+        assert_eq!(
+            rename_identifiers(code, &renames(&["styles"])),
+            "export const Child = () => <div className={styles_Child.root} />;\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_key_of_a_destructured_binding() {
+        let code = "const { size } = props;\nconst b = size;\n";
+        assert_eq!(
+            rename_identifiers(code, &renames(&["size"])),
+            "const { size: size_Child } = props;\nconst b = size_Child;\n"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    fs::{read_dir, remove_dir_all, remove_file, rename},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::utils::{
+    structs::{AppState, StoredIndex, StoredMerge},
+    utils::{
+        check_dir_exist_or_create, extract_repo_info, git_output, read_from_file_ut,
+        read_repo_config,
+    },
+};
+
+pub const INDEX_STORE_DIR: &str = "/etc/compo-doc/index";
+
+// bumped whenever StoredIndex or StoredMerge changes, a restart then syncs
+// and merges again
+pub const STORED_INDEX_VERSION: u32 = 2;
+
+// indexes kept per repository, the oldest are deleted
+const MAX_STORED_INDEXES: usize = 5;
+
+fn index_path(repo: &str, commit: &str) -> String {
+    format!("{INDEX_STORE_DIR}/{repo}/{commit}.json")
+}
+
+// the merged components of a commit, deleted along with its index
+fn merged_dir(index_path: &Path) -> std::path::PathBuf {
+    index_path.with_extension("merged")
+}
+
+fn merged_path(repo: &str, commit: &str, component: &str) -> std::path::PathBuf {
+    merged_dir(Path::new(&index_path(repo, commit))).join(merged_file_name(component))
+}
+
+/// One flat file name per component, whatever its path
+fn merged_file_name(component: &str) -> String {
+    let mut name = String::new();
+    for byte in component.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'_' | b'-' => name.push(byte as char),
+            _ => name.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    name.push_str(".json");
+    name
+}
+
+/// Save the index of a commit, written to a temporary file first so a crash
+/// never leaves a truncated index behind
+pub fn save_index(index: &StoredIndex) -> Result<(), String> {
+    let path = index_path(&index.repo, &index.commit);
+    let tmp_path = format!("{path}.tmp");
+
+    check_dir_exist_or_create(&path);
+    let content = serde_json::to_vec(index).map_err(|err| err.to_string())?;
+    std::fs::write(&tmp_path, content).map_err(|err| err.to_string())?;
+    rename(&tmp_path, &path).map_err(|err| err.to_string())?;
+
+    prune_indexes(&index.repo);
+    Ok(())
+}
+
+/// Keep the most recent indexes of a repository
+fn prune_indexes(repo: &str) {
+    let Ok(entries) = read_dir(format!("{INDEX_STORE_DIR}/{repo}")) else {
+        return;
+    };
+
+    let mut files: Vec<(std::time::SystemTime, std::path::PathBuf)> = entries
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    for (_, path) in files.into_iter().skip(MAX_STORED_INDEXES) {
+        let _ = remove_dir_all(merged_dir(&path));
+        let _ = remove_file(path);
+    }
+}
+
+pub fn load_index(repo: &str, commit: &str) -> Option<StoredIndex> {
+    let content = read_from_file_ut(&index_path(repo, commit)).ok()?;
+    match parse_index(&content, commit) {
+        Ok(index) => Some(index),
+        Err(err) => {
+            println!("ignoring the stored index of {repo}@{commit} : {err}");
+            None
+        }
+    }
+}
+
+fn parse_index(content: &str, commit: &str) -> Result<StoredIndex, String> {
+    #[derive(serde::Deserialize)]
+    struct Version {
+        version: Option<u32>,
+    }
+
+    // read first, an index of another version may not parse at all
+    let version = serde_json::from_str::<Version>(content)
+        .map_err(|err| err.to_string())?
+        .version;
+    if version != Some(STORED_INDEX_VERSION) {
+        return Err(format!(
+            "version {} instead of {STORED_INDEX_VERSION}",
+            version.map_or("none".to_string(), |v| v.to_string())
+        ));
+    }
+
+    let index: StoredIndex = serde_json::from_str(content).map_err(|err| err.to_string())?;
+    if index.commit != commit {
+        return Err(format!("saved for {}", index.commit));
+    }
+    Ok(index)
+}
+
+/// Merged code of a component at a commit, `None` when it was never saved
+pub fn load_merged(repo: &str, commit: &str, component: &str) -> Option<StoredMerge> {
+    read_merged(&merged_path(repo, commit, component))
+}
+
+/// Save the merged code of a component, a restart then serves it as is
+pub fn save_merged(
+    repo: &str,
+    commit: &str,
+    component: &str,
+    code: &str,
+    sources: &[String],
+) -> Result<(), String> {
+    let merged = StoredMerge {
+        version: STORED_INDEX_VERSION,
+        code: code.to_string(),
+        sources: sources.to_vec(),
+    };
+    write_merged(&merged_path(repo, commit, component), &merged)
+}
+
+fn read_merged(path: &Path) -> Option<StoredMerge> {
+    let content = std::fs::read(path).ok()?;
+    serde_json::from_slice::<StoredMerge>(&content)
+        .ok()
+        .filter(|merged| merged.version == STORED_INDEX_VERSION)
+}
+
+fn write_merged(path: &Path, merged: &StoredMerge) -> Result<(), String> {
+    let tmp_path = path.with_extension("json.tmp");
+
+    std::fs::create_dir_all(path.parent().ok_or("no parent directory")?)
+        .map_err(|err| err.to_string())?;
+    let content = serde_json::to_vec(merged).map_err(|err| err.to_string())?;
+    std::fs::write(&tmp_path, content).map_err(|err| err.to_string())?;
+    rename(&tmp_path, path).map_err(|err| err.to_string())
+}
+
+/// Index of the commit checked out for the configured repository, `None`
+/// when the clone is missing or was never indexed
+pub fn load_current_index() -> Option<StoredIndex> {
+    let repo_config = read_repo_config().ok()?;
+    let (_username, repo, _branch) = extract_repo_info(&repo_config)?;
+
+    let repo_dir = format!("/etc/compo-doc/tmp/{repo}");
+    if !Path::new(&repo_dir).exists() {
+        return None;
+    }
+    let commit = git_output(&repo_dir, &["rev-parse", "HEAD"]).ok()?;
+
+    load_index(repo, commit.trim())
+}
+
+/// Stop serving the index, the repository it belongs to is no longer configured
+pub fn clear_index(state: &mut AppState) {
+    state.curr_ver.clear();
+    state.comp_liste.clear();
+    state.diagnostics.clear();
+    state.props.clear();
+    state.examples.clear();
+    Arc::make_mut(&mut state.search_index).sync(Vec::new());
+}
+
+/// Serve a stored index, the search index is rebuilt from its documents
+pub fn restore_index(state: &mut AppState, index: StoredIndex) {
+    state.curr_ver = index.commit;
+    state.comp_liste = index.components;
+    state.diagnostics = index.diagnostics;
+    state.props = index.props;
+    state.examples = index.examples;
+    Arc::make_mut(&mut state.search_index).sync(index.documents);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_json(version: Option<u32>, commit: &str) -> String {
+        let mut index = serde_json::json!({
+            "repo": "lib",
+            "commit": commit,
+            "synced_at": 0,
+            "components": [],
+            "props": {},
+            "dependencies": {},
+            "examples": {},
+            "documents": [],
+            "diagnostics": [],
+        });
+        if let Some(version) = version {
+            index["version"] = version.into();
+        }
+        index.to_string()
+    }
+
+    #[test]
+    fn reads_an_index_of_the_current_version() {
+        let index = parse_index(&index_json(Some(STORED_INDEX_VERSION), "abc"), "abc").unwrap();
+        assert_eq!(index.repo, "lib");
+    }
+
+    #[test]
+    fn ignores_the_other_versions_and_commits() {
+        assert!(parse_index(&index_json(None, "abc"), "abc").is_err());
+        assert!(parse_index(&index_json(Some(STORED_INDEX_VERSION + 1), "abc"), "abc").is_err());
+        assert!(parse_index(&index_json(Some(STORED_INDEX_VERSION), "abc"), "def").is_err());
+        assert!(parse_index("{\"version\": 1, \"repo\": 3}", "abc").is_err());
+    }
+
+    #[test]
+    fn keeps_one_merged_file_per_component() {
+        assert_eq!(merged_file_name("Button.tsx"), "Button.tsx.json");
+        assert_eq!(
+            merged_file_name("forms/Input.tsx"),
+            "forms%2FInput.tsx.json"
+        );
+        assert_eq!(
+            merged_path("lib", "abc", "../x").to_string_lossy(),
+            format!("{INDEX_STORE_DIR}/lib/abc.merged/..%2Fx.json")
+        );
+    }
+
+    #[test]
+    fn reads_back_the_merged_code_of_the_current_version() {
+        let dir = std::env::temp_dir().join(format!("compo-doc-merged-{}", std::process::id()));
+        let path = dir.join("abc.merged").join(merged_file_name("Button.tsx"));
+
+        let mut merged = StoredMerge {
+            version: STORED_INDEX_VERSION,
+            code: "const Button = 1;".to_string(),
+            sources: vec!["components/Button.tsx".to_string()],
+        };
+        write_merged(&path, &merged).unwrap();
+        let read = read_merged(&path).unwrap();
+        assert_eq!(read.code, merged.code);
+        assert_eq!(read.sources, merged.sources);
+
+        merged.version = STORED_INDEX_VERSION + 1;
+        write_merged(&path, &merged).unwrap();
+        assert!(read_merged(&path).is_none());
+        assert!(read_merged(&dir.join("missing.json")).is_none());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::utils::{
+    annotations::{
+        SourceComment, component_doc_comment, find_marker, jsdoc_tag, legacy_from_comments,
+        parse_marker_attributes, source_comments,
+    },
+    storybook::{CsfFile, csf_component_name},
+    structs::{ComponentMeta, ComponentStatus, ComponentsList, StorybookInfo},
+};
+
+/// Build the index entry of a component file during sync
+pub fn index_component(name: &str, content: &str) -> ComponentsList {
+    let comments = source_comments(content);
+
+    let legacy = legacy_from_comments(content, &comments);
+    let mut meta = meta_from_comments(content, &comments);
+
+    // a legacy annotation always wins over the declared status
+    if legacy.is_some() {
+        meta.status = Some(ComponentStatus::Legacy);
+    }
+
+    ComponentsList {
+        name: name.to_string(),
+        is_legacy: legacy.is_some() || meta.status == Some(ComponentStatus::Legacy),
+        legacy,
+        meta,
+        storybook: Vec::new(),
+        updated_at: None,
+    }
+}
+
+/// Read the `//<meta ...>` marker, completed by the `@category`, `@status`,
+/// `@owner` and `@keywords` tags of the component JSDoc
+pub fn meta_from_comments(content: &str, comments: &[SourceComment]) -> ComponentMeta {
+    let mut meta = ComponentMeta::default();
+
+    if let Some(marker) = find_marker(comments, "meta") {
+        let attrs = parse_marker_attributes(&marker.text);
+        meta.category = attrs.get("category").cloned();
+        meta.status = attrs.get("status").and_then(|s| ComponentStatus::parse(s));
+        meta.owner = attrs.get("owner").cloned();
+        meta.keywords = attrs
+            .get("keywords")
+            .map(|k| split_keywords(k))
+            .unwrap_or_default();
+    }
+
+    if let Some(doc) = component_doc_comment(content, comments) {
+        if meta.category.is_none() {
+            meta.category = jsdoc_tag(&doc.text, "category").filter(|c| !c.is_empty());
+        }
+        if meta.status.is_none() {
+            meta.status = jsdoc_tag(&doc.text, "status").and_then(|s| ComponentStatus::parse(&s));
+        }
+        if meta.owner.is_none() {
+            meta.owner = jsdoc_tag(&doc.text, "owner").filter(|o| !o.is_empty());
+        }
+        if meta.keywords.is_empty() {
+            meta.keywords = jsdoc_tag(&doc.text, "keywords")
+                .map(|k| split_keywords(&k))
+                .unwrap_or_default();
+        }
+    }
+
+    meta
+}
+
+fn split_keywords(raw: &str) -> Vec<String> {
+    raw.split([',', ' '])
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Attach the CSF files of the repository to the components they document
+pub fn attach_storybook(liste: &mut [ComponentsList], csf_files: &[(String, CsfFile)]) {
+    for (file, csf) in csf_files {
+        let Some(component) = csf_component_name(file, csf) else {
+            continue;
+        };
+
+        let entry = liste
+            .iter_mut()
+            .find(|c| c.name.split('.').next() == Some(component.as_str()));
+
+        if let Some(entry) = entry {
+            entry.storybook.push(StorybookInfo {
+                file: file.clone(),
+                title: csf.title.clone(),
+                stories: csf.stories.iter().map(|s| s.name.clone()).collect(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completes_the_meta_marker_with_the_jsdoc_tags() {
+        let code = r#"//<meta category="Forms" keywords="input, field">
+/**
+ * @category Layout
+ * @status experimental
+ * @owner team-ui
+ */
+export const Input = () => <input />;
+"#;
+        let entry = index_component("Input.tsx", code);
+        assert_eq!(entry.meta.category.as_deref(), Some("Forms"));
+        assert_eq!(entry.meta.status, Some(ComponentStatus::Experimental));
+        assert_eq!(entry.meta.owner.as_deref(), Some("team-ui"));
+        assert_eq!(entry.meta.keywords, ["input", "field"]);
+        assert!(!entry.is_legacy);
+    }
+
+    #[test]
+    fn marks_the_legacy_components() {
+        let code = "//<meta status=\"stable\">\n//<legacy>\nexport const Old = () => <div />;\n";
+        let entry = index_component("Old.tsx", code);
+        assert_eq!(entry.meta.status, Some(ComponentStatus::Legacy));
+        assert!(entry.is_legacy);
+
+        let code = "/** @status deprecated */\nexport const Old = () => <div />;\n";
+        let entry = index_component("Old.tsx", code);
+        assert!(entry.is_legacy && entry.legacy.is_none());
+
+        let code = "/** @status unknown */\nexport const New = () => <div />;\n";
+        assert_eq!(index_component("New.tsx", code).meta.status, None);
+    }
+}
@@ -0,0 +1,364 @@
+use swc_ecma_ast::{
+    ImportSpecifier, JSXAttrName, JSXAttrOrSpread, JSXAttrValue, JSXElement, JSXElementName,
+    JSXExpr, JSXMemberExpr, JSXObject, ModuleDecl, ModuleExportName, ModuleItem,
+};
+use swc_ecma_visit::{Visit, VisitWith};
+
+use crate::utils::{
+    code_merge::get_import_specifiers,
+    examples::CODE_FILE_EXTENSIONS,
+    tsx_parser::{ParsedFile, literal_value},
+    utils::normalize_path,
+};
+
+/// One prop written on a JSX element
+#[derive(Debug, Clone)]
+pub struct JsxProp {
+    pub name: String,
+    // literal value as written (`"primary"`, `3`, `true`), None when dynamic
+    pub value: Option<String>,
+}
+
+/// One `<Component ...>` found in a file
+#[derive(Debug, Clone)]
+pub struct JsxUsage {
+    pub line: usize,
+    pub props: Vec<JsxProp>,
+    pub has_spread: bool,
+    pub code: String,
+}
+
+/// Find every JSX element of `file` rendering the component `component` of
+/// `target` (both relative to the repository root). Only the names the file
+/// imports from `target` count: `<Button>`, `<Btn>` for `{ Button as Btn }`
+/// or `<UI.Button>` for `* as UI`, never a `<Button>` of another library.
+pub fn find_jsx_usages(code: &str, file: &str, target: &str, component: &str) -> Vec<JsxUsage> {
+    // cheap check before parsing the whole file
+    let specs = get_import_specifiers(code);
+    if !specs
+        .iter()
+        .any(|spec| import_resolves_to(file, spec, target) != ImportTarget::Other)
+    {
+        return Vec::new();
+    }
+
+    let parsed = match ParsedFile::parse(code) {
+        Ok(parsed) => parsed,
+        Err(_) => return Vec::new(),
+    };
+
+    let bindings = component_bindings(&parsed, file, target, component);
+    if bindings.locals.is_empty() && bindings.namespaces.is_empty() {
+        return Vec::new();
+    }
+
+    let mut finder = JsxUsageFinder {
+        component,
+        bindings: &bindings,
+        parsed: &parsed,
+        usages: Vec::new(),
+    };
+    parsed.module.visit_with(&mut finder);
+
+    finder.usages
+}
+
+/// `file` imports the component `component` of `target`, by name, as the
+/// default export or through a namespace
+pub fn imports_component(code: &str, file: &str, target: &str, component: &str) -> bool {
+    let specs = get_import_specifiers(code);
+    if !specs
+        .iter()
+        .any(|spec| import_resolves_to(file, spec, target) != ImportTarget::Other)
+    {
+        return false;
+    }
+
+    match ParsedFile::parse(code) {
+        Ok(parsed) => {
+            let bindings = component_bindings(&parsed, file, target, component);
+            !bindings.locals.is_empty() || !bindings.namespaces.is_empty()
+        }
+        Err(_) => false,
+    }
+}
+
+/// What an import specifier points at, seen from the component file
+#[derive(Debug, PartialEq, Eq)]
+pub enum ImportTarget {
+    // the component file itself
+    Component,
+    // the `index` of its folder, re-exporting it
+    Barrel,
+    Other,
+}
+
+/// Resolve the import `spec` of `file` against `target` (both relative to the
+/// repository root), through a relative path or an alias ending with the
+/// target path (`@/components/Button`)
+pub fn import_resolves_to(file: &str, spec: &str, target: &str) -> ImportTarget {
+    let target = strip_extension(target);
+    let target_dir = target.strip_suffix("/index").unwrap_or(target);
+    let barrel = target_dir.rsplit_once('/').map(|(d, _)| d).unwrap_or("");
+    let dir = file.rsplit_once('/').map(|(d, _)| d).unwrap_or("");
+
+    let spec = strip_extension(spec);
+    let spec = spec.strip_suffix("/index").unwrap_or(spec);
+    let matches = |path: &str| {
+        if spec.starts_with('.') {
+            normalize_path(&format!("{dir}/{spec}")) == path
+        } else {
+            // aliases can not be resolved without the bundler config
+            let Some((_, rest)) = spec.split_once('/') else {
+                return false;
+            };
+            !path.is_empty() && (path == rest || path.ends_with(&format!("/{rest}")))
+        }
+    };
+
+    if matches(target) || matches(target_dir) {
+        ImportTarget::Component
+    } else if matches(barrel) {
+        ImportTarget::Barrel
+    } else {
+        ImportTarget::Other
+    }
+}
+
+pub fn strip_extension(path: &str) -> &str {
+    CODE_FILE_EXTENSIONS
+        .iter()
+        .find_map(|ext| path.strip_suffix(&format!(".{ext}")))
+        .unwrap_or(path)
+}
+
+/// Names a file gives the component through its imports
+#[derive(Debug, Default)]
+struct ComponentBindings {
+    // `<Button>`
+    locals: Vec<String>,
+    // `<UI.Button>`
+    namespaces: Vec<String>,
+}
+
+fn component_bindings(
+    parsed: &ParsedFile,
+    file: &str,
+    target: &str,
+    component: &str,
+) -> ComponentBindings {
+    let mut bindings = ComponentBindings::default();
+
+    for item in &parsed.module.body {
+        let ModuleItem::ModuleDecl(ModuleDecl::Import(import)) = item else {
+            continue;
+        };
+        let resolved = import_resolves_to(file, &import.src.value, target);
+        if resolved == ImportTarget::Other {
+            continue;
+        }
+
+        for specifier in &import.specifiers {
+            match specifier {
+                // the default export of a barrel is not the component
+                ImportSpecifier::Default(default) if resolved == ImportTarget::Component => {
+                    bindings.locals.push(default.local.sym.to_string());
+                }
+                ImportSpecifier::Default(_) => {}
+                ImportSpecifier::Named(named) => {
+                    let imported = match &named.imported {
+                        Some(ModuleExportName::Ident(ident)) => ident.sym.to_string(),
+                        Some(ModuleExportName::Str(name)) => name.value.to_string(),
+                        None => named.local.sym.to_string(),
+                    };
+                    if imported == component {
+                        bindings.locals.push(named.local.sym.to_string());
+                    }
+                }
+                ImportSpecifier::Namespace(namespace) => {
+                    bindings.namespaces.push(namespace.local.sym.to_string());
+                }
+            }
+        }
+    }
+
+    bindings
+}
+
+struct JsxUsageFinder<'a> {
+    component: &'a str,
+    bindings: &'a ComponentBindings,
+    parsed: &'a ParsedFile,
+    usages: Vec<JsxUsage>,
+}
+
+impl JsxUsageFinder<'_> {
+    fn renders_component(&self, name: &JSXElementName) -> bool {
+        match name {
+            JSXElementName::Ident(ident) => self.bindings.locals.iter().any(|l| ident.sym == *l),
+            JSXElementName::JSXMemberExpr(JSXMemberExpr {
+                obj: JSXObject::Ident(obj),
+                prop,
+                ..
+            }) => {
+                prop.sym == *self.component
+                    && self.bindings.namespaces.iter().any(|n| obj.sym == *n)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Visit for JsxUsageFinder<'_> {
+    fn visit_jsx_element(&mut self, n: &JSXElement) {
+        if self.renders_component(&n.opening.name) {
+            let mut props = Vec::new();
+            let mut has_spread = false;
+
+            for attr in &n.opening.attrs {
+                match attr {
+                    JSXAttrOrSpread::JSXAttr(attr) => {
+                        let name = match &attr.name {
+                            JSXAttrName::Ident(ident) => ident.sym.to_string(),
+                            JSXAttrName::JSXNamespacedName(ns) => {
+                                format!("{}:{}", ns.ns.sym, ns.name.sym)
+                            }
+                        };
+                        let value = match &attr.value {
+                            // a bare attribute is `true`
+                            None => Some("true".to_string()),
+                            Some(JSXAttrValue::Lit(lit)) => {
+                                literal_value(&swc_ecma_ast::Expr::Lit(lit.clone()))
+                            }
+                            Some(JSXAttrValue::JSXExprContainer(container)) => {
+                                match &container.expr {
+                                    JSXExpr::Expr(expr) => literal_value(expr),
+                                    JSXExpr::JSXEmptyExpr(_) => None,
+                                }
+                            }
+                            _ => None,
+                        };
+                        props.push(JsxProp { name, value });
+                    }
+                    JSXAttrOrSpread::SpreadElement(_) => has_spread = true,
+                }
+            }
+
+            // children are a prop too
+            if !n.children.is_empty() {
+                props.push(JsxProp {
+                    name: "children".to_string(),
+                    value: None,
+                });
+            }
+
+            self.usages.push(JsxUsage {
+                line: self.parsed.line_of(n.span),
+                props,
+                has_spread,
+                code: self.parsed.snippet(n.span),
+            });
+        }
+
+        n.visit_children_with(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "components/Button.tsx";
+
+    fn usages(file: &str, code: &str) -> Vec<JsxUsage> {
+        find_jsx_usages(code, file, TARGET, "Button")
+    }
+
+    #[test]
+    fn finds_the_elements_of_the_imported_component() {
+        let code = r#"import { Button } from "../components/Button";
+export const Page = () => <Button variant="primary" size={2} disabled>Go</Button>;
+"#;
+        let found = usages("pages/Page.tsx", code);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].line, 2);
+
+        let props: Vec<(&str, Option<&str>)> = found[0]
+            .props
+            .iter()
+            .map(|p| (p.name.as_str(), p.value.as_deref()))
+            .collect();
+        assert_eq!(
+            props,
+            [
+                ("variant", Some("\"primary\"")),
+                ("size", Some("2")),
+                ("disabled", Some("true")),
+                ("children", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_a_component_of_the_same_name_from_elsewhere() {
+        let code = r#"import { Button } from "@mui/material";
+export const Page = () => <Button {...props} />;
+"#;
+        assert!(usages("pages/Page.tsx", code).is_empty());
+
+        // not imported at all
+        let code = "export const Page = () => <Button />;\n";
+        assert!(usages("pages/Page.tsx", code).is_empty());
+    }
+
+    #[test]
+    fn follows_renamed_default_and_namespace_imports() {
+        let code = r#"import Btn from "../components/Button";
+import { Button as Other } from "ui-kit";
+export const Page = () => <><Btn /><Other /></>;
+"#;
+        assert_eq!(usages("pages/Page.tsx", code).len(), 1);
+
+        let code = r#"import * as UI from "@/components";
+export const Page = () => <><UI.Button /><UI.Card /></>;
+"#;
+        assert_eq!(usages("src/pages/Page.tsx", code).len(), 1);
+
+        let code = r#"import { Button } from "./";
+export const Page = () => <Button />;
+"#;
+        assert_eq!(usages("components/Page.tsx", code).len(), 1);
+    }
+
+    #[test]
+    fn resolves_the_import_specifiers() {
+        let resolve = |file, spec| import_resolves_to(file, spec, TARGET);
+
+        assert_eq!(
+            resolve("a/b.tsx", "../components/Button"),
+            ImportTarget::Component
+        );
+        assert_eq!(
+            resolve("a/b.tsx", "../components/Button.tsx"),
+            ImportTarget::Component
+        );
+        assert_eq!(resolve("a/b.tsx", "../components"), ImportTarget::Barrel);
+        assert_eq!(
+            resolve("a/b.tsx", "@/components/Button"),
+            ImportTarget::Component
+        );
+        assert_eq!(
+            resolve("a/b.tsx", "./components/Button"),
+            ImportTarget::Other
+        );
+        assert_eq!(resolve("a/b.tsx", "react"), ImportTarget::Other);
+        assert_eq!(
+            import_resolves_to("a.tsx", "./Card", "components/Card/index.tsx"),
+            ImportTarget::Other
+        );
+        assert_eq!(
+            import_resolves_to("a.tsx", "./components/Card", "components/Card/index.tsx"),
+            ImportTarget::Component
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::{fs::symlink_metadata, path::Path, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::utils::structs::LimitDiagnostic;
+
+pub const LIMITS_PATH: &str = "/etc/compo-doc/config/limits.json";
+
+/// Bounds on the processing of a repository, the repositories are untrusted
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ResourceLimits {
+    // bytes on disk once cloned, .git included
+    pub max_repo_size: u64,
+    // bytes, larger files are never parsed
+    pub max_file_size: u64,
+    pub max_components: usize,
+    // components merged into each other
+    pub max_merge_depth: usize,
+    // bytes of merged code for one component
+    pub max_merged_size: u64,
+    // bytes produced by all the merge steps of one component
+    pub max_merge_work: u64,
+    pub git_timeout_secs: u64,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        ResourceLimits {
+            max_repo_size: 500 * 1024 * 1024,
+            max_file_size: 1024 * 1024,
+            max_components: 2000,
+            max_merge_depth: 16,
+            max_merged_size: 5 * 1024 * 1024,
+            max_merge_work: 50 * 1024 * 1024,
+            git_timeout_secs: 120,
+        }
+    }
+}
+
+static LIMITS: OnceLock<ResourceLimits> = OnceLock::new();
+
+/// Read `limits.json`, the defaults apply when it is missing
+pub fn load_limits(path: &str) -> Result<ResourceLimits, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(ResourceLimits::default());
+        }
+        Err(err) => return Err(format!("could not read {path} : {err}")),
+    };
+
+    let limits: ResourceLimits =
+        serde_json::from_str(&content).map_err(|err| format!("invalid {path} : {err}"))?;
+
+    if limits.max_repo_size == 0
+        || limits.max_file_size == 0
+        || limits.max_components == 0
+        || limits.max_merge_depth == 0
+        || limits.max_merged_size == 0
+        || limits.max_merge_work == 0
+        || limits.git_timeout_secs == 0
+    {
+        return Err(format!("{path} : every limit must be positive"));
+    }
+
+    Ok(limits)
+}
+
+/// Use these limits for the rest of the run, only the first call counts
+pub fn set_limits(limits: ResourceLimits) {
+    let _ = LIMITS.set(limits);
+}
+
+pub fn limits() -> &'static ResourceLimits {
+    LIMITS.get_or_init(ResourceLimits::default)
+}
+
+fn check(limit: &str, subject: &str, value: u64, max: u64) -> Result<(), LimitDiagnostic> {
+    if value <= max {
+        return Ok(());
+    }
+    let diagnostic = LimitDiagnostic {
+        limit: limit.to_string(),
+        subject: subject.to_string(),
+        value,
+        max,
+    };
+    println!("{}", diagnostic.message());
+    Err(diagnostic)
+}
+
+pub fn check_file_size(subject: &str, size: usize) -> Result<(), LimitDiagnostic> {
+    check(
+        "max_file_size",
+        subject,
+        size as u64,
+        limits().max_file_size,
+    )
+}
+
+pub fn check_component_count(count: usize) -> Result<(), LimitDiagnostic> {
+    check(
+        "max_components",
+        "components",
+        count as u64,
+        limits().max_components as u64,
+    )
+}
+
+pub fn check_merge_depth(subject: &str, depth: usize) -> Result<(), LimitDiagnostic> {
+    check(
+        "max_merge_depth",
+        subject,
+        depth as u64,
+        limits().max_merge_depth as u64,
+    )
+}
+
+pub fn check_merged_size(subject: &str, size: usize) -> Result<(), LimitDiagnostic> {
+    check(
+        "max_merged_size",
+        subject,
+        size as u64,
+        limits().max_merged_size,
+    )
+}
+
+pub fn check_merge_work(subject: &str, work: u64) -> Result<(), LimitDiagnostic> {
+    check("max_merge_work", subject, work, limits().max_merge_work)
+}
+
+/// Size of the cloned repository, the walk stops as soon as the limit is passed
+pub fn check_repo_size(repo_dir: &str) -> Result<u64, LimitDiagnostic> {
+    let max = limits().max_repo_size;
+    let mut total = 0u64;
+    let mut pending = vec![Path::new(repo_dir).to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            // symlinks are counted, never followed
+            let Ok(meta) = symlink_metadata(entry.path()) else {
+                continue;
+            };
+            if meta.is_dir() {
+                pending.push(entry.path());
+            } else {
+                total += meta.len();
+            }
+            if total > max {
+                check("max_repo_size", repo_dir, total, max)?;
+            }
+        }
+    }
+
+    Ok(total)
+}
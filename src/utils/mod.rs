@@ -1,7 +1,32 @@
 // file exporting the modules for use in other fn's
+pub mod annotations;
+pub mod api_diff;
+pub mod audit;
+pub mod auth;
+pub mod bundler;
+pub mod cli;
 pub mod code_merge;
+pub mod component_cache;
+pub mod config_validation;
+pub mod dead_code;
+pub mod examples;
 pub mod handler;
+pub mod history;
 pub mod http_utils;
+pub mod import_merge;
+pub mod index_store;
+pub mod indexer;
+pub mod jsx_usage;
+pub mod limits;
+pub mod npm_deps;
+pub mod rate_limit;
+pub mod search;
+pub mod source_map;
+pub mod storybook;
 pub mod structs;
+pub mod style_collector;
+pub mod sync_pipeline;
+pub mod tsx_parser;
 pub mod type_extractor;
+pub mod usages;
 pub mod utils;
@@ -0,0 +1,489 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::utils::{
+    code_merge::get_import_specifiers,
+    structs::NpmDependency,
+    utils::{normalize_path, read_repo_file},
+};
+
+/// Packages big enough to be worth a warning when a component pulls them in
+pub const HEAVY_PACKAGES: [&str; 14] = [
+    "moment",
+    "lodash",
+    "rxjs",
+    "three",
+    "d3",
+    "chart.js",
+    "echarts",
+    "plotly.js",
+    "monaco-editor",
+    "@mui/material",
+    "antd",
+    "firebase",
+    "aws-sdk",
+    "pdfjs-dist",
+];
+
+// extensions tried when resolving a repo-local import without one
+const CODE_EXTENSIONS: [&str; 4] = ["tsx", "ts", "jsx", "js"];
+
+// the usual aliases of the repo sources, when the tsconfig does not declare them
+const DEFAULT_ALIASES: [&str; 2] = ["@/*", "~/*"];
+
+/// List the third-party packages a component needs, following its repo-local
+/// imports transitively, with the versions declared in `package.json` and
+/// resolved by the lockfile
+pub fn component_npm_dependencies(repo_root: &str, entry_path: &str) -> Vec<NpmDependency> {
+    let imports = collect_package_imports(repo_root, entry_path);
+    let manifest = PackageManifest::load(repo_root);
+
+    imports
+        .into_iter()
+        .map(|(name, imported_by)| {
+            let (requested, dependency_type) = match manifest.declared.get(&name) {
+                Some((range, kind)) => (Some(range.clone()), Some(kind.clone())),
+                None => (None, None),
+            };
+
+            NpmDependency {
+                heavy: HEAVY_PACKAGES.contains(&name.as_str()),
+                resolved: manifest.locked.get(&name).cloned(),
+                requested,
+                dependency_type,
+                imported_by,
+                name,
+            }
+        })
+        .collect()
+}
+
+/// package name -> repo files importing it
+pub fn collect_package_imports(repo_root: &str, entry_path: &str) -> BTreeMap<String, Vec<String>> {
+    let mut packages: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let aliases = PathAliases::load(repo_root);
+    let mut visited = HashSet::new();
+    let mut queue = vec![entry_path.to_string()];
+
+    while let Some(file) = queue.pop() {
+        if !visited.insert(file.clone()) {
+            continue;
+        }
+
+        let code = match read_repo_file(repo_root, &file) {
+            Ok(code) => code,
+            Err(_) => continue,
+        };
+
+        let file_dir = match file.rsplit_once('/') {
+            Some((dir, _)) => dir.to_string(),
+            None => String::new(),
+        };
+
+        for spec in get_import_specifiers(&code) {
+            if spec.starts_with('.') {
+                if let Some(local) = resolve_local_module(repo_root, &file_dir, &spec) {
+                    queue.push(local);
+                }
+                continue;
+            }
+
+            // `@/components/Icon` is a repo file, not the `@` package
+            if let Some(paths) = aliases.resolve(&spec) {
+                if let Some(local) = paths
+                    .iter()
+                    .find_map(|path| resolve_local_module(repo_root, "", path))
+                {
+                    queue.push(local);
+                }
+                continue;
+            }
+
+            if let Some(name) = package_name(&spec) {
+                let importers = packages.entry(name).or_default();
+                if !importers.contains(&file) {
+                    importers.push(file.clone());
+                }
+            }
+        }
+    }
+
+    packages
+}
+
+/// `@scope/pkg/sub/path` -> `@scope/pkg`, `pkg/sub` -> `pkg`,
+/// node builtins, absolute paths and aliases (`@/`, `~/`) have no package
+pub fn package_name(spec: &str) -> Option<String> {
+    if spec.starts_with(['/', '~']) || spec.starts_with("node:") || spec.contains("://") {
+        return None;
+    }
+
+    let mut parts = spec.split('/');
+    let first = parts.next()?;
+
+    if first == "@" {
+        return None;
+    }
+    if first.starts_with('@') {
+        let second = parts.next()?;
+        return Some(format!("{first}/{second}"));
+    }
+
+    Some(first.to_string())
+}
+
+fn resolve_local_module(repo_root: &str, file_dir: &str, spec: &str) -> Option<String> {
+    let base = normalize_path(&format!("{file_dir}/{spec}"));
+
+    // styles, images, json ... are not code and never import packages
+    let has_code_ext = CODE_EXTENSIONS
+        .iter()
+        .any(|ext| base.ends_with(&format!(".{ext}")));
+    if has_code_ext {
+        return Some(base);
+    }
+
+    let mut candidates = Vec::new();
+    for ext in CODE_EXTENSIONS {
+        candidates.push(format!("{base}.{ext}"));
+        candidates.push(format!("{base}/index.{ext}"));
+    }
+
+    candidates
+        .into_iter()
+        .find(|c| Path::new(&format!("{repo_root}/{c}")).is_file())
+}
+
+/// Import aliases of the `compilerOptions.paths` of the tsconfig (or
+/// jsconfig), `@/*` -> `src/*`, followed by the default ones
+#[derive(Debug, Default)]
+pub struct PathAliases {
+    // (pattern, repo paths it maps to), a `*` in both is the matched part
+    aliases: Vec<(String, Vec<String>)>,
+}
+
+impl PathAliases {
+    pub fn load(repo_root: &str) -> Self {
+        let mut aliases = ["tsconfig.json", "jsconfig.json"]
+            .iter()
+            .find_map(|file| read_repo_file(repo_root, file).ok())
+            .map(|content| PathAliases::parse(&content))
+            .unwrap_or_default();
+
+        for pattern in DEFAULT_ALIASES {
+            if !aliases.aliases.iter().any(|(p, _)| p == pattern) {
+                aliases.aliases.push((
+                    pattern.to_string(),
+                    vec!["src/*".to_string(), "*".to_string()],
+                ));
+            }
+        }
+
+        aliases
+    }
+
+    fn parse(content: &str) -> Self {
+        let json: Value = match serde_json::from_str(&strip_json_comments(content)) {
+            Ok(json) => json,
+            Err(err) => {
+                println!("could not parse the tsconfig : {err}");
+                return PathAliases::default();
+            }
+        };
+
+        let options = &json["compilerOptions"];
+        let base_url = options["baseUrl"].as_str().unwrap_or(".");
+        let Some(paths) = options["paths"].as_object() else {
+            return PathAliases::default();
+        };
+
+        let aliases = paths
+            .iter()
+            .map(|(pattern, targets)| {
+                let targets = targets
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(|target| normalize_path(&format!("{base_url}/{target}")))
+                    .collect();
+                (pattern.clone(), targets)
+            })
+            .collect();
+
+        PathAliases { aliases }
+    }
+
+    /// Repo paths the import may point at, None when it is not an alias
+    pub fn resolve(&self, spec: &str) -> Option<Vec<String>> {
+        self.aliases.iter().find_map(|(pattern, targets)| {
+            let matched = match pattern.split_once('*') {
+                Some((prefix, suffix)) => spec
+                    .strip_prefix(prefix)
+                    .and_then(|rest| rest.strip_suffix(suffix))?,
+                None if spec == pattern => "",
+                None => return None,
+            };
+            Some(
+                targets
+                    .iter()
+                    .map(|target| target.replacen('*', matched, 1))
+                    .collect(),
+            )
+        })
+    }
+}
+
+/// The tsconfig is JSON with comments and trailing commas
+fn strip_json_comments(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => while chars.next_if(|&c| c != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    let trailing_comma_re = Regex::new(r",(\s*[}\]])").unwrap();
+    trailing_comma_re.replace_all(&out, "$1").to_string()
+}
+
+/// What the repository declares and locks in its package files
+#[derive(Debug, Default)]
+pub struct PackageManifest {
+    // name -> (requested range, dependencies | devDependencies | peerDependencies)
+    pub declared: HashMap<String, (String, String)>,
+    // name -> version resolved by the lockfile
+    pub locked: HashMap<String, String>,
+}
+
+impl PackageManifest {
+    pub fn load(repo_root: &str) -> Self {
+        let mut manifest = PackageManifest::default();
+
+        if let Ok(content) = read_repo_file(repo_root, "package.json") {
+            manifest.read_package_json(&content);
+        }
+
+        if let Ok(content) = read_repo_file(repo_root, "package-lock.json") {
+            manifest.read_npm_lock(&content);
+        } else if let Ok(content) = read_repo_file(repo_root, "yarn.lock") {
+            manifest.read_yarn_lock(&content);
+        } else if let Ok(content) = read_repo_file(repo_root, "pnpm-lock.yaml") {
+            manifest.read_pnpm_lock(&content);
+        }
+
+        manifest
+    }
+
+    fn read_package_json(&mut self, content: &str) {
+        let json: Value = match serde_json::from_str(content) {
+            Ok(json) => json,
+            Err(err) => {
+                println!("could not parse package.json : {err}");
+                return;
+            }
+        };
+
+        // the first section wins, runtime dependencies matter most
+        for kind in ["dependencies", "peerDependencies", "devDependencies"] {
+            if let Some(Value::Object(deps)) = json.get(kind) {
+                for (name, range) in deps {
+                    self.declared.entry(name.clone()).or_insert((
+                        range.as_str().unwrap_or_default().to_string(),
+                        kind.to_string(),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn read_npm_lock(&mut self, content: &str) {
+        let json: Value = match serde_json::from_str(content) {
+            Ok(json) => json,
+            Err(err) => {
+                println!("could not parse package-lock.json : {err}");
+                return;
+            }
+        };
+
+        // lockfile v2 / v3
+        if let Some(Value::Object(packages)) = json.get("packages") {
+            for (path, info) in packages {
+                // only the top level install is the one the app resolves
+                let Some(name) = path.strip_prefix("node_modules/") else {
+                    continue;
+                };
+                if name.contains("/node_modules/") {
+                    continue;
+                }
+                if let Some(version) = info.get("version").and_then(Value::as_str) {
+                    self.locked.insert(name.to_string(), version.to_string());
+                }
+            }
+        }
+
+        // lockfile v1
+        if let Some(Value::Object(deps)) = json.get("dependencies") {
+            for (name, info) in deps {
+                if let Some(version) = info.get("version").and_then(Value::as_str) {
+                    self.locked
+                        .entry(name.clone())
+                        .or_insert(version.to_string());
+                }
+            }
+        }
+    }
+
+    fn read_yarn_lock(&mut self, content: &str) {
+        // "@scope/pkg@^1.0.0", "@scope/pkg@~1.1.0":
+        //   version "1.1.3"
+        let entry_re = Regex::new(r#"^"?((?:@[^@/"\s]+/)?[^@"\s]+)@"#).unwrap();
+        let version_re = Regex::new(r#"^\s+version:?\s+"?([^"\s]+)"?"#).unwrap();
+
+        let mut current: Option<String> = None;
+        for line in content.lines() {
+            if !line.starts_with(' ') && !line.starts_with('#') {
+                current = entry_re.captures(line).map(|c| c[1].to_string());
+                continue;
+            }
+            if let (Some(name), Some(cap)) = (&current, version_re.captures(line)) {
+                self.locked
+                    .entry(name.clone())
+                    .or_insert(cap[1].to_string());
+                current = None;
+            }
+        }
+    }
+
+    fn read_pnpm_lock(&mut self, content: &str) {
+        //   /@scope/pkg@1.2.3:  |  /pkg/1.2.3:  |  pkg@1.2.3:
+        let entry_re =
+            Regex::new(r#"^\s{2}'?/?((?:@[^@/\s]+/)?[^@/\s:']+)[@/](\d[^(:'\s]*)"#).unwrap();
+
+        for line in content.lines() {
+            if let Some(cap) = entry_re.captures(line) {
+                self.locked
+                    .entry(cap[1].to_string())
+                    .or_insert(cap[2].to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_the_packages_but_not_the_aliases() {
+        assert_eq!(
+            package_name("react-dom/client").as_deref(),
+            Some("react-dom")
+        );
+        assert_eq!(
+            package_name("@mui/material/Button").as_deref(),
+            Some("@mui/material")
+        );
+        assert_eq!(package_name("@/components/Icon"), None);
+        assert_eq!(package_name("~/utils"), None);
+        assert_eq!(package_name("node:fs"), None);
+    }
+
+    #[test]
+    fn reads_the_paths_of_a_commented_tsconfig() {
+        let aliases = PathAliases::parse(
+            r#"{
+  // the app sources
+  "$schema": "https://json.schemastore.org/tsconfig",
+  "compilerOptions": {
+    "baseUrl": "./app", /* relative to the config */
+    "paths": {
+      "@components/*": ["components/*", "legacy/*",],
+      "config": ["config/index.ts"],
+    },
+  },
+}"#,
+        );
+
+        assert_eq!(
+            aliases.resolve("@components/Button"),
+            Some(vec![
+                "app/components/Button".to_string(),
+                "app/legacy/Button".to_string()
+            ])
+        );
+        assert_eq!(
+            aliases.resolve("config"),
+            Some(vec!["app/config/index.ts".to_string()])
+        );
+        assert_eq!(aliases.resolve("@components-kit/core"), None);
+        assert_eq!(aliases.resolve("react"), None);
+    }
+
+    #[test]
+    fn follows_the_aliased_imports_instead_of_reporting_them() {
+        let root = std::env::temp_dir().join(format!("compo-doc-npm-deps-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src/components")).unwrap();
+        std::fs::create_dir_all(root.join("src/ui")).unwrap();
+
+        let files = [
+            (
+                "tsconfig.json",
+                r#"{ "compilerOptions": { "paths": { "$ui/*": ["src/ui/*"] } } }"#,
+            ),
+            (
+                "src/components/Button.tsx",
+                "import React from \"react\";\nimport { Icon } from \"@/components/Icon\";\nimport { Box } from \"$ui/Box\";\n",
+            ),
+            (
+                "src/components/Icon.tsx",
+                "import { format } from \"date-fns\";\n",
+            ),
+            ("src/ui/Box.tsx", "import clsx from \"clsx\";\n"),
+        ];
+        for (path, code) in files {
+            std::fs::write(root.join(path), code).unwrap();
+        }
+
+        let packages = collect_package_imports(root.to_str().unwrap(), "src/components/Button.tsx");
+        let names: Vec<&str> = packages.keys().map(String::as_str).collect();
+        assert_eq!(names, ["clsx", "date-fns", "react"]);
+        assert_eq!(packages["date-fns"], ["src/components/Icon.tsx"]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
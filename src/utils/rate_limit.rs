@@ -0,0 +1,363 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{Response, StatusCode, header},
+    middleware::Next,
+};
+use serde::Deserialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::utils::{http_utils::json_response_builder, structs::JsonResponse};
+
+pub const RATE_LIMITS_PATH: &str = "/etc/compo-doc/config/rate_limits.json";
+
+// the oldest buckets are dropped past this, a client spraying requests never
+// grows the table
+const MAX_BUCKETS: usize = 10_000;
+// the requests matching no route share one limit
+const FALLBACK_ROUTE: &str = "fallback";
+// what a client waits for when all the merge, report or sync slots are busy
+const BUSY_RETRY_AFTER_SECS: u64 = 2;
+
+/// Requests a client can make on a route
+#[derive(Debug, Deserialize, Clone)]
+pub struct RouteLimit {
+    pub per_minute: u32,
+    // requests allowed at once before the rate applies
+    pub burst: u32,
+}
+
+impl RouteLimit {
+    fn new(per_minute: u32, burst: u32) -> Self {
+        RouteLimit { per_minute, burst }
+    }
+}
+
+/// Content of `rate_limits.json`, every field falls back to the defaults
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    // routes without their own limit
+    pub default: RouteLimit,
+    // keyed by route pattern (`/components/{id}`)
+    pub routes: HashMap<String, RouteLimit>,
+    // the handlers take a slot around their work, the cached answers none
+    pub max_concurrent_merges: usize,
+    // the usages, history, changelog, compare and dead code reports
+    pub max_concurrent_reports: usize,
+    pub max_concurrent_syncs: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let routes = HashMap::from([
+            ("/components".to_string(), RouteLimit::new(60, 20)),
+            ("/components/{id}".to_string(), RouteLimit::new(60, 20)),
+            (
+                "/components/{id}/bundle".to_string(),
+                RouteLimit::new(60, 20),
+            ),
+            (
+                "/components/{id}/usages".to_string(),
+                RouteLimit::new(30, 10),
+            ),
+            (
+                "/components/{id}/history".to_string(),
+                RouteLimit::new(30, 10),
+            ),
+            ("/compare".to_string(), RouteLimit::new(30, 10)),
+            ("/changelog".to_string(), RouteLimit::new(30, 10)),
+            ("/reports/dead-code".to_string(), RouteLimit::new(10, 5)),
+            ("/sync".to_string(), RouteLimit::new(6, 2)),
+            ("/config".to_string(), RouteLimit::new(10, 5)),
+        ]);
+
+        RateLimitConfig {
+            default: RouteLimit::new(120, 60),
+            routes,
+            max_concurrent_merges: 4,
+            max_concurrent_reports: 2,
+            max_concurrent_syncs: 1,
+        }
+    }
+}
+
+/// Read `rate_limits.json`, the defaults apply when it is missing
+pub fn load_rate_limits(path: &str) -> Result<RateLimitConfig, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(RateLimitConfig::default());
+        }
+        Err(err) => return Err(format!("could not read {path} : {err}")),
+    };
+
+    let config: RateLimitConfig =
+        serde_json::from_str(&content).map_err(|err| format!("invalid {path} : {err}"))?;
+
+    let limits = config.routes.values().chain([&config.default]);
+    if limits
+        .into_iter()
+        .any(|l| l.per_minute == 0 || l.burst == 0)
+    {
+        return Err(format!("{path} : per_minute and burst must be positive"));
+    }
+    if config.max_concurrent_merges == 0
+        || config.max_concurrent_reports == 0
+        || config.max_concurrent_syncs == 0
+    {
+        return Err(format!("{path} : the concurrency caps must be positive"));
+    }
+
+    Ok(config)
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// (client, route) -> bucket, with the keys in creation order for the eviction
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<(String, String), Bucket>,
+    order: VecDeque<(String, String)>,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<Buckets>,
+    merges: Arc<Semaphore>,
+    reports: Arc<Semaphore>,
+    syncs: Arc<Semaphore>,
+}
+
+impl RateLimiter {
+    fn slots(&self) -> WorkSlots {
+        WorkSlots {
+            merges: self.merges.clone(),
+            reports: self.reports.clone(),
+            syncs: self.syncs.clone(),
+        }
+    }
+
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            merges: Arc::new(Semaphore::new(config.max_concurrent_merges)),
+            reports: Arc::new(Semaphore::new(config.max_concurrent_reports)),
+            syncs: Arc::new(Semaphore::new(config.max_concurrent_syncs)),
+            buckets: Mutex::new(Buckets::default()),
+            config,
+        }
+    }
+
+    fn limit_for(&self, route: &str) -> &RouteLimit {
+        self.config
+            .routes
+            .get(route)
+            .unwrap_or(&self.config.default)
+    }
+
+    /// Take a token for the client on the route, or the seconds to wait
+    fn take_token(&self, client: &str, route: &str, limit: &RouteLimit) -> Result<(), u64> {
+        let rate = limit.per_minute as f64 / 60.0;
+        let capacity = limit.burst as f64;
+        let now = Instant::now();
+
+        let mut table = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let Buckets { buckets, order } = &mut *table;
+
+        let key = (client.to_string(), route.to_string());
+        if !buckets.contains_key(&key) {
+            while buckets.len() >= MAX_BUCKETS {
+                let Some(oldest) = order.pop_front() else {
+                    break;
+                };
+                buckets.remove(&oldest);
+            }
+            order.push_back(key.clone());
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            last: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / rate).ceil().max(1.0) as u64)
+        }
+    }
+}
+
+pub type SharedRateLimiter = Arc<RateLimiter>;
+
+/// The global caps on the merges, reports and syncs. The handlers take a slot
+/// around the work itself, so the cached answers never wait for one.
+#[derive(Clone)]
+pub struct WorkSlots {
+    merges: Arc<Semaphore>,
+    reports: Arc<Semaphore>,
+    syncs: Arc<Semaphore>,
+}
+
+impl WorkSlots {
+    /// A slot held until dropped, none when all of them are busy
+    pub fn merge(&self) -> Option<OwnedSemaphorePermit> {
+        self.merges.clone().try_acquire_owned().ok()
+    }
+
+    /// The usages, history, changelog, compare and dead code reports
+    pub fn report(&self) -> Option<OwnedSemaphorePermit> {
+        self.reports.clone().try_acquire_owned().ok()
+    }
+
+    pub fn sync(&self) -> Option<OwnedSemaphorePermit> {
+        self.syncs.clone().try_acquire_owned().ok()
+    }
+}
+
+fn too_many_requests(message: &str, retry_after: u64) -> Response<Body> {
+    let mut response = json_response_builder(
+        StatusCode::TOO_MANY_REQUESTS,
+        JsonResponse::<String>::make_error(message.to_string()),
+    );
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, retry_after.into());
+    response
+}
+
+/// Answer to the requests finding all the slots of their cap busy
+pub fn busy() -> Response<Body> {
+    too_many_requests(
+        "the server is busy with other requests, retry shortly",
+        BUSY_RETRY_AFTER_SECS,
+    )
+}
+
+/// Middleware applying the route limit per client and handing the global caps
+/// to the handlers. It runs before the authentication, so the clients are the
+/// peer addresses and the failed logins are limited too.
+pub async fn rate_limit(
+    State(limiter): State<SharedRateLimiter>,
+    mut request: Request,
+    next: Next,
+) -> Response<Body> {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => FALLBACK_ROUTE.to_string(),
+    };
+
+    let client = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "unknown".to_string(),
+    };
+
+    let limit = limiter.limit_for(&route).clone();
+
+    if let Err(retry_after) = limiter.take_token(&client, &route, &limit) {
+        println!("rate limited {client} on {route}");
+        return too_many_requests("too many requests, slow down", retry_after);
+    }
+
+    request.extensions_mut().insert(limiter.slots());
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("compo-doc-{name}-{}", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn refuses_empty_limits_and_caps() {
+        let path = write_config(
+            "rate-limits",
+            r#"{"routes": {"/compare": {"per_minute": 0, "burst": 1}}}"#,
+        );
+        assert!(load_rate_limits(&path).unwrap_err().contains("positive"));
+
+        std::fs::write(&path, r#"{"max_concurrent_reports": 0}"#).unwrap();
+        assert!(load_rate_limits(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            load_rate_limits("/nonexistent/rate_limits.json")
+                .unwrap()
+                .max_concurrent_reports,
+            2
+        );
+    }
+
+    #[test]
+    fn limits_each_client_to_its_burst() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        let limit = RouteLimit::new(60, 2);
+
+        assert!(limiter.take_token("ip:1.1.1.1", "/sync", &limit).is_ok());
+        assert!(limiter.take_token("ip:1.1.1.1", "/sync", &limit).is_ok());
+        assert_eq!(limiter.take_token("ip:1.1.1.1", "/sync", &limit), Err(1));
+        // the other clients keep their own bucket
+        assert!(limiter.take_token("ip:2.2.2.2", "/sync", &limit).is_ok());
+    }
+
+    #[test]
+    fn bounds_the_bucket_table() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        let limit = RouteLimit::new(60, 1);
+
+        assert!(limiter.take_token("ip:first", "/sync", &limit).is_ok());
+        assert!(limiter.take_token("ip:first", "/sync", &limit).is_err());
+        for i in 0..MAX_BUCKETS {
+            let _ = limiter.take_token(&format!("ip:{i}"), "/sync", &limit);
+        }
+
+        let table = limiter.buckets.lock().unwrap();
+        assert_eq!(table.buckets.len(), MAX_BUCKETS);
+        assert_eq!(table.order.len(), MAX_BUCKETS);
+        // the oldest bucket made room for the last one
+        assert!(
+            !table
+                .buckets
+                .contains_key(&("ip:first".to_string(), "/sync".to_string()))
+        );
+    }
+
+    #[test]
+    fn hands_the_caps_to_the_handlers() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        let slots = limiter.slots();
+
+        let held = slots.sync().unwrap();
+        assert!(limiter.syncs.clone().try_acquire_owned().is_err());
+        assert!(slots.sync().is_none());
+        drop(held);
+        assert!(slots.sync().is_some());
+
+        let merges: Vec<_> = (0..4).filter_map(|_| slots.merge()).collect();
+        assert_eq!(merges.len(), 4);
+        assert!(slots.merge().is_none());
+        // the caps are independent
+        assert!(slots.report().is_some());
+    }
+}
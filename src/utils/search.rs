@@ -0,0 +1,567 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Bound,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    annotations::source_comments,
+    structs::{ComponentsList, PropInfo, SearchHit},
+    type_extractor::{TypeExtractor, find_used_type},
+};
+
+/// Longest query accepted, in characters
+pub const MAX_QUERY_LEN: usize = 200;
+/// Words of a query past this one are ignored
+pub const MAX_QUERY_TOKENS: usize = 8;
+
+/// Where a token was found, ordered by how much a match there is worth
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchField {
+    Name,
+    Meta,
+    Prop,
+    Doc,
+    Code,
+}
+
+impl SearchField {
+    fn weight(&self) -> f64 {
+        match self {
+            SearchField::Name => 8.0,
+            SearchField::Meta => 4.0,
+            SearchField::Prop => 3.0,
+            SearchField::Doc => 2.0,
+            SearchField::Code => 0.5,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            SearchField::Name => "name",
+            SearchField::Meta => "meta",
+            SearchField::Prop => "props",
+            SearchField::Doc => "docs",
+            SearchField::Code => "code",
+        }
+    }
+}
+
+/// Everything searchable about one component
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SearchDocument {
+    pub component: String,
+    pub fields: Vec<(SearchField, String)>,
+}
+
+impl SearchDocument {
+    /// Build the document from the source and the index entry of a component
+    pub fn from_source(entry: &ComponentsList, code: &str) -> Self {
+        let mut fields = vec![(SearchField::Name, entry.name.clone())];
+
+        let meta = &entry.meta;
+        let mut meta_text = meta.keywords.join(" ");
+        for value in [&meta.category, &meta.owner].into_iter().flatten() {
+            meta_text.push(' ');
+            meta_text.push_str(value);
+        }
+        fields.push((SearchField::Meta, meta_text));
+
+        for storybook in &entry.storybook {
+            let mut stories_text = storybook.stories.join(" ");
+            if let Some(title) = &storybook.title {
+                stories_text.push(' ');
+                stories_text.push_str(title);
+            }
+            fields.push((SearchField::Doc, stories_text));
+        }
+
+        for prop in extract_props(code) {
+            fields.push((
+                SearchField::Prop,
+                format!("{} {}", prop.name, prop.prop_type),
+            ));
+            if let Some(description) = prop.description {
+                fields.push((SearchField::Doc, description));
+            }
+        }
+
+        for comment in source_comments(code) {
+            if comment.is_block && comment.text.starts_with('*') {
+                fields.push((SearchField::Doc, comment.text));
+            }
+        }
+
+        fields.push((SearchField::Code, code.to_string()));
+
+        SearchDocument {
+            component: entry.name.clone(),
+            fields,
+        }
+    }
+
+    fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (field, text) in &self.fields {
+            field.hash(&mut hasher);
+            text.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/// Props of the type used by the component, empty when none is found
+pub fn extract_props(code: &str) -> Vec<PropInfo> {
+    let Ok(Some(type_name)) = find_used_type(code) else {
+        return Vec::new();
+    };
+
+    let mut extractor = TypeExtractor::new(&type_name);
+    match extractor.extract_from_str(code) {
+        Ok(_) => extractor.found_props.unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IndexedDocument {
+    hash: u64,
+    // token -> count per field
+    tokens: HashMap<String, HashMap<SearchField, u32>>,
+    // first lines of the docs, shown with the results
+    snippet: String,
+}
+
+/// In-memory inverted index over the components, rebuilt document by
+/// document so a sync only re-tokenizes the files that changed
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    documents: HashMap<String, IndexedDocument>,
+    // token -> components containing it, sorted so the prefixes are a range
+    postings: BTreeMap<String, HashSet<String>>,
+    // length in chars -> tokens, the only ones a typo can reach
+    lengths: HashMap<usize, HashSet<String>>,
+}
+
+impl SearchIndex {
+    /// Replace the indexed documents with `documents`, returns how many were
+    /// (re)indexed
+    pub fn sync(&mut self, documents: Vec<SearchDocument>) -> usize {
+        let names: HashSet<String> = documents.iter().map(|d| d.component.clone()).collect();
+
+        let removed: Vec<String> = self
+            .documents
+            .keys()
+            .filter(|name| !names.contains(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            self.remove(&name);
+        }
+
+        documents
+            .into_iter()
+            .filter(|document| self.upsert(document))
+            .count()
+    }
+
+    /// Index a document unless the same content is already indexed
+    pub fn upsert(&mut self, document: &SearchDocument) -> bool {
+        let hash = document.content_hash();
+        if self
+            .documents
+            .get(&document.component)
+            .is_some_and(|d| d.hash == hash)
+        {
+            return false;
+        }
+
+        self.remove(&document.component);
+
+        let mut tokens: HashMap<String, HashMap<SearchField, u32>> = HashMap::new();
+        for (field, text) in &document.fields {
+            for token in tokenize(text) {
+                *tokens.entry(token).or_default().entry(*field).or_default() += 1;
+            }
+        }
+
+        for token in tokens.keys() {
+            let components = self.postings.entry(token.clone()).or_default();
+            if components.is_empty() {
+                self.lengths
+                    .entry(token.chars().count())
+                    .or_default()
+                    .insert(token.clone());
+            }
+            components.insert(document.component.clone());
+        }
+
+        let snippet = document
+            .fields
+            .iter()
+            .find(|(field, _)| *field == SearchField::Doc)
+            .map(|(_, text)| clean_snippet(text))
+            .unwrap_or_default();
+
+        self.documents.insert(
+            document.component.clone(),
+            IndexedDocument {
+                hash,
+                tokens,
+                snippet,
+            },
+        );
+        true
+    }
+
+    pub fn remove(&mut self, component: &str) {
+        let Some(document) = self.documents.remove(component) else {
+            return;
+        };
+        for token in document.tokens.keys() {
+            if let Some(components) = self.postings.get_mut(token) {
+                components.remove(component);
+                if components.is_empty() {
+                    self.postings.remove(token);
+                    let length = token.chars().count();
+                    if let Some(tokens) = self.lengths.get_mut(&length) {
+                        tokens.remove(token);
+                        if tokens.is_empty() {
+                            self.lengths.remove(&length);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Rank the components for a free text query, tolerating typos
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let query: String = query.chars().take(MAX_QUERY_LEN).collect();
+        let mut query_tokens = tokenize(&query);
+        let mut seen = HashSet::new();
+        query_tokens.retain(|token| seen.insert(token.clone()));
+        query_tokens.truncate(MAX_QUERY_TOKENS);
+        if query_tokens.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scores: HashMap<&str, (f64, HashSet<&'static str>, usize)> = HashMap::new();
+
+        for query_token in &query_tokens {
+            // every indexed token close enough to the query one
+            let mut matched_components: HashSet<&str> = HashSet::new();
+
+            for token in self.candidates(query_token) {
+                let closeness = match_closeness(query_token, token);
+                if closeness == 0.0 {
+                    continue;
+                }
+                let components = &self.postings[token];
+
+                for component in components {
+                    let document = &self.documents[component];
+                    let Some(fields) = document.tokens.get(token) else {
+                        continue;
+                    };
+
+                    let entry =
+                        scores
+                            .entry(component.as_str())
+                            .or_insert((0.0, HashSet::new(), 0));
+                    for (field, count) in fields {
+                        entry.0 += closeness * field.weight() * (1.0 + (*count as f64).ln());
+                        entry.1.insert(field.label());
+                    }
+                    matched_components.insert(component.as_str());
+                }
+            }
+
+            for component in matched_components {
+                if let Some(entry) = scores.get_mut(component) {
+                    entry.2 += 1;
+                }
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .map(|(component, (score, fields, matched_tokens))| {
+                // documents matching every word of the query come first
+                let coverage = matched_tokens as f64 / query_tokens.len() as f64;
+                let mut matched_fields: Vec<String> =
+                    fields.into_iter().map(str::to_string).collect();
+                matched_fields.sort();
+
+                SearchHit {
+                    component: component.to_string(),
+                    score: (score * coverage * coverage * 100.0).round() / 100.0,
+                    matched_fields,
+                    snippet: self.documents[component].snippet.clone(),
+                }
+            })
+            .collect();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.component.cmp(&b.component))
+        });
+        hits.truncate(limit);
+        hits
+    }
+
+    /// Indexed tokens `match_closeness` can accept for a query token: the
+    /// token itself, the ones it prefixes and the ones of a length a typo
+    /// can reach, so the edit distance never runs over the whole index
+    fn candidates(&self, query_token: &str) -> HashSet<&str> {
+        let mut candidates = HashSet::new();
+
+        if let Some((token, _)) = self.postings.get_key_value(query_token) {
+            candidates.insert(token.as_str());
+        }
+
+        if query_token.len() >= 3 {
+            candidates.extend(
+                self.postings
+                    .range::<str, _>((Bound::Included(query_token), Bound::Unbounded))
+                    .map(|(token, _)| token.as_str())
+                    .take_while(|token| token.starts_with(query_token)),
+            );
+        }
+
+        let length = query_token.chars().count();
+        let allowed = typo_allowance(length);
+        if allowed > 0 {
+            for candidate_length in length - allowed..=length + allowed {
+                if let Some(tokens) = self.lengths.get(&candidate_length) {
+                    candidates.extend(tokens.iter().map(String::as_str));
+                }
+            }
+        }
+
+        candidates
+    }
+}
+
+/// Lowercase words of a text, camelCase and snake_case split apart
+/// (`PrimaryButton` gives `primarybutton`, `primary` and `button`)
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.len() < 2 {
+            continue;
+        }
+
+        let mut part = String::new();
+        let mut previous_lower = false;
+        let mut parts = Vec::new();
+        for c in word.chars() {
+            if c.is_uppercase() && previous_lower && !part.is_empty() {
+                parts.push(std::mem::take(&mut part));
+            }
+            previous_lower = c.is_lowercase() || c.is_ascii_digit();
+            part.push(c);
+        }
+        parts.push(part);
+
+        tokens.push(word.to_lowercase());
+        if parts.len() > 1 {
+            for part in parts {
+                if part.len() >= 2 {
+                    tokens.push(part.to_lowercase());
+                }
+            }
+        }
+    }
+
+    tokens
+}
+
+/// 1.0 for an exact match, less for a prefix or a close typo, 0.0 otherwise
+fn match_closeness(query: &str, token: &str) -> f64 {
+    if query == token {
+        return 1.0;
+    }
+    if query.len() >= 3 && token.starts_with(query) {
+        return 0.6;
+    }
+
+    let allowed = typo_allowance(query.chars().count());
+    if allowed == 0 || token.chars().count().abs_diff(query.chars().count()) > allowed {
+        return 0.0;
+    }
+
+    match bounded_levenshtein(query, token, allowed) {
+        Some(1) => 0.5,
+        Some(_) => 0.3,
+        None => 0.0,
+    }
+}
+
+/// Typos tolerated in a query word: one from 4 letters, two from 8
+fn typo_allowance(length: usize) -> usize {
+    match length {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Edit distance between two words, `None` once it goes over `max`
+fn bounded_levenshtein(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        let mut row_min = current[0];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+            row_min = row_min.min(current[j + 1]);
+        }
+        if row_min > max {
+            return None;
+        }
+        previous = current;
+    }
+
+    let distance = previous[b.len()];
+    if distance <= max {
+        Some(distance)
+    } else {
+        None
+    }
+}
+
+fn clean_snippet(doc: &str) -> String {
+    let text = doc
+        .lines()
+        .map(|l| l.trim().trim_start_matches('*').trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('@'))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    text.chars().take(160).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::indexer::index_component;
+
+    const BUTTON: &str = r#"
+type ButtonProps = {
+  /** text of the button */
+  label: string;
+  size?: "sm" | "lg";
+};
+/** Primary call to action */
+export default function PrimaryButton({ label, size }: ButtonProps) {
+  return <button className={size}>{label}</button>;
+}
+"#;
+
+    #[test]
+    fn extracts_the_props_of_the_used_type() {
+        let props = extract_props(BUTTON);
+        let names: Vec<&str> = props.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["label", "size"]);
+        assert!(props[1].optional);
+        assert_eq!(props[0].description.as_deref(), Some("text of the button"));
+    }
+
+    #[test]
+    fn has_no_props_without_a_type_or_a_parse() {
+        assert!(extract_props("export default function Divider() { return <hr />; }").is_empty());
+        assert!(extract_props("export default function ( {{ <").is_empty());
+    }
+
+    #[test]
+    fn splits_camel_and_snake_case() {
+        assert_eq!(
+            tokenize("PrimaryButton icon_size a"),
+            vec!["primarybutton", "primary", "button", "icon", "size"]
+        );
+    }
+
+    #[test]
+    fn tolerates_typos_by_word_length() {
+        assert_eq!(match_closeness("button", "button"), 1.0);
+        assert_eq!(match_closeness("butt", "button"), 0.6);
+        assert_eq!(match_closeness("buton", "button"), 0.5);
+        assert_eq!(match_closeness("btn", "bin"), 0.0);
+        assert_eq!(bounded_levenshtein("kitten", "sitting", 2), None);
+        assert_eq!(bounded_levenshtein("kitten", "sitting", 3), Some(3));
+    }
+
+    #[test]
+    fn ranks_the_name_matches_first() {
+        let mut index = SearchIndex::default();
+        index.sync(vec![
+            SearchDocument::from_source(&index_component("PrimaryButton.tsx", BUTTON), BUTTON),
+            SearchDocument::from_source(
+                &index_component("Card.tsx", "// renders a button\nexport const Card = 1;"),
+                "// renders a button\nexport const Card = 1;",
+            ),
+        ]);
+
+        let hits = index.search("buton", 10);
+        assert_eq!(hits[0].component, "PrimaryButton.tsx");
+        assert!(hits[0].matched_fields.contains(&"name".to_string()));
+        assert!(index.search("zz", 10).is_empty());
+    }
+
+    #[test]
+    fn only_compares_the_reachable_tokens() {
+        let mut index = SearchIndex::default();
+        index.sync(vec![SearchDocument::from_source(
+            &index_component("PrimaryButton.tsx", BUTTON),
+            BUTTON,
+        )]);
+
+        let candidates = index.candidates("buton");
+        assert!(candidates.contains("button"));
+        assert!(!candidates.contains("primarybutton"));
+        assert!(index.candidates("prim").contains("primarybutton"));
+
+        index.sync(Vec::new());
+        assert!(index.postings.is_empty() && index.lengths.is_empty());
+    }
+
+    #[test]
+    fn caps_the_query() {
+        let mut index = SearchIndex::default();
+        index.sync(vec![SearchDocument::from_source(
+            &index_component("PrimaryButton.tsx", BUTTON),
+            BUTTON,
+        )]);
+
+        // the matching word comes after the allowed number of tokens
+        let padding = (0..MAX_QUERY_TOKENS)
+            .map(|i| format!("zz{i}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert!(index.search(&format!("{padding} button"), 10).is_empty());
+        assert!(!index.search(&format!("button {padding}"), 10).is_empty());
+
+        let long = format!("{} button", "z".repeat(MAX_QUERY_LEN));
+        assert!(index.search(&long, 10).is_empty());
+    }
+}
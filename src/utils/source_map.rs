@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use regex::Regex;
+use sourcemap::SourceMapBuilder;
+
+use crate::utils::utils::read_repo_file;
+
+// one file being walked through while reading the merged output
+struct OpenSource {
+    src_id: u32,
+    cursor: usize,
+    lines: Vec<String>,
+}
+
+/// Build a v3 source map for the output of `merge_with_sources`.
+///
+/// The merged code keeps the original lines (minus the stripped exports), so
+/// every output line is matched against the next equivalent line of the file
+/// that owns the current `//<merged from="...">` block.
+/// `entry_path` and the `from` paths are relative to `repo_root`,
+/// `source_root` is prefixed to them by consumers (e.g. a link to the file at
+/// the synced commit).
+/// The markers are part of the repository content, only the ones naming a
+/// file of `sources` (what the merger inlined) open a block.
+pub fn build_source_map(
+    merged: &str,
+    sources: &[String],
+    entry_path: &str,
+    repo_root: &str,
+    source_root: Option<&str>,
+) -> Result<serde_json::Value> {
+    let open_re = Regex::new(r#"^\s*//<merged from="([^"]+)""#).unwrap();
+    let close_re = Regex::new(r#"^\s*//</merged>"#).unwrap();
+
+    let mut builder = SourceMapBuilder::new(None);
+    builder.set_source_root(source_root);
+
+    let mut sources_cache: HashMap<String, Vec<String>> = HashMap::new();
+    let mut stack = vec![open_source(
+        &mut builder,
+        &mut sources_cache,
+        entry_path,
+        repo_root,
+    )];
+
+    for (dst_line, line) in merged.lines().enumerate() {
+        if let Some(cap) = open_re.captures(line)
+            && sources.iter().any(|source| source == &cap[1])
+        {
+            stack.push(open_source(
+                &mut builder,
+                &mut sources_cache,
+                &cap[1],
+                repo_root,
+            ));
+            continue;
+        }
+
+        if close_re.is_match(line) {
+            // never pop the entry file
+            if stack.len() > 1 {
+                stack.pop();
+            }
+            continue;
+        }
+
+        let out = line.trim();
+        if out.is_empty() {
+            continue;
+        }
+
+        let current = stack.last_mut().unwrap();
+
+        // stripped exports only shorten the start of a line
+        let found = current.lines[current.cursor..]
+            .iter()
+            .position(|src| src.trim_end().ends_with(out));
+
+        if let Some(offset) = found {
+            let src_line = current.cursor + offset;
+            let src = current.lines[src_line].trim_end();
+            let src_col = src.len() - out.len();
+            let dst_col = line.len() - line.trim_start().len();
+
+            builder.add_raw(
+                dst_line as u32,
+                dst_col as u32,
+                src_line as u32,
+                src_col as u32,
+                Some(current.src_id),
+                None,
+                false,
+            );
+            current.cursor = src_line + 1;
+        }
+    }
+
+    let mut buf = Vec::new();
+    builder.into_sourcemap().to_writer(&mut buf)?;
+
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+fn open_source(
+    builder: &mut SourceMapBuilder,
+    sources_cache: &mut HashMap<String, Vec<String>>,
+    path: &str,
+    repo_root: &str,
+) -> OpenSource {
+    let lines = sources_cache
+        .entry(path.to_string())
+        .or_insert_with(|| match read_repo_file(repo_root, path) {
+            Ok(content) => content.lines().map(str::to_string).collect(),
+            Err(err) => {
+                println!("could not read {path} for the source map : {err}");
+                Vec::new()
+            }
+        })
+        .clone();
+
+    let src_id = builder.add_source(path);
+    if !builder.has_source_contents(src_id) {
+        builder.set_source_contents(src_id, Some(&lines.join("\n")));
+    }
+
+    OpenSource {
+        src_id,
+        cursor: 0,
+        lines,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_clone(name: &str) -> (std::path::PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("compo-doc-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("repo/components")).unwrap();
+        std::fs::write(
+            dir.join("repo/components/Card.tsx"),
+            "import Badge from \"./Badge\";\nexport default function Card() {\n  return <Badge />;\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("repo/components/Badge.tsx"),
+            "export default function Badge() {\n  return <b />;\n}\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("hostname"), "server-secret").unwrap();
+        let root = dir.join("repo").to_string_lossy().to_string();
+        (dir, root)
+    }
+
+    const MERGED: &str = "function Card() {\n  return <Badge />;\n}\n//<merged from=\"components/Badge.tsx\">\nfunction Badge() {\n  return <b />;\n}\n//</merged>\n";
+
+    fn sources_of(map: &serde_json::Value) -> Vec<String> {
+        serde_json::from_value(map["sources"].clone()).unwrap()
+    }
+
+    #[test]
+    fn maps_the_merged_blocks_to_their_files() {
+        let (dir, root) = fake_clone("map");
+        let map = build_source_map(
+            MERGED,
+            &["components/Badge.tsx".to_string()],
+            "components/Card.tsx",
+            &root,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sources_of(&map),
+            vec!["components/Card.tsx", "components/Badge.tsx"]
+        );
+        let decoded = sourcemap::SourceMap::from_slice(map.to_string().as_bytes()).unwrap();
+        // `function Badge()` comes from the first line of Badge.tsx
+        let token = decoded.lookup_token(4, 0).unwrap();
+        assert_eq!(token.get_source(), Some("components/Badge.tsx"));
+        assert_eq!(token.get_src_line(), 0);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn ignores_the_markers_the_merger_did_not_write() {
+        let (dir, root) = fake_clone("map-forged");
+        let forged = "//<merged from=\"../../../../etc/hostname\">\n//<merged from=\"../hostname\">\nfunction Card() {}\n//</merged>\n//</merged>\n";
+
+        let map = build_source_map(forged, &[], "components/Card.tsx", &root, None).unwrap();
+
+        assert_eq!(sources_of(&map), vec!["components/Card.tsx"]);
+        assert!(!map.to_string().contains("server-secret"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn never_reads_a_recorded_source_leaving_the_clone() {
+        let (dir, root) = fake_clone("map-symlink");
+        std::fs::remove_file(dir.join("repo/components/Badge.tsx")).unwrap();
+        std::os::unix::fs::symlink(dir.join("hostname"), dir.join("repo/components/Badge.tsx"))
+            .unwrap();
+
+        let map = build_source_map(
+            MERGED,
+            &["components/Badge.tsx".to_string()],
+            "components/Card.tsx",
+            &root,
+            None,
+        )
+        .unwrap();
+
+        assert!(!map.to_string().contains("server-secret"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
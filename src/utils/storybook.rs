@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+use swc_ecma_ast::{
+    AssignTarget, Decl, ExportDefaultExpr, Expr, Lit, MemberProp, ModuleDecl, ModuleItem,
+    ObjectLit, Pat, Prop, PropName, PropOrSpread, SimpleAssignTarget, Stmt,
+};
+
+use crate::utils::{
+    jsx_usage::JsxProp,
+    structs::PropInfo,
+    tsx_parser::{ParsedFile, literal_value},
+};
+
+/// A Component Story Format file
+#[derive(Debug, Clone, Default)]
+pub struct CsfFile {
+    pub title: Option<String>,
+    // identifier given to the `component` field of the default export
+    pub component: Option<String>,
+    pub arg_types: Vec<CsfArgType>,
+    pub stories: Vec<CsfStory>,
+}
+
+/// One entry of the meta `argTypes`
+#[derive(Debug, Clone, Default)]
+pub struct CsfArgType {
+    pub name: String,
+    pub description: Option<String>,
+    // select | radio | boolean | text ...
+    pub control: Option<String>,
+    pub options: Vec<String>,
+    pub default_value: Option<String>,
+    // `type: "string"` / `type: { name: "string", required: true }`
+    pub type_name: Option<String>,
+    pub required: bool,
+}
+
+/// One named story export
+#[derive(Debug, Clone)]
+pub struct CsfStory {
+    pub export_name: String,
+    // `name` / `storyName` when set, the export name otherwise
+    pub name: String,
+    pub args: Vec<JsxProp>,
+    pub line: usize,
+    pub code: String,
+}
+
+pub fn is_story_file(path: &str) -> bool {
+    [".stories.tsx", ".stories.ts", ".stories.jsx", ".stories.js"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+}
+
+/// Parse the default export meta and the story exports of a CSF file
+pub fn parse_csf(code: &str) -> Option<CsfFile> {
+    let parsed = ParsedFile::parse(code).ok()?;
+    let mut csf = CsfFile::default();
+
+    // top level `const x = ...`, the meta is often declared before its export
+    let mut consts: HashMap<String, &Expr> = HashMap::new();
+    // `Story.args = {...}` / `Story.storyName = "..."` (CSF 2)
+    let mut assigned: HashMap<(String, String), &Expr> = HashMap::new();
+
+    for item in &parsed.module.body {
+        let var = match item {
+            ModuleItem::Stmt(Stmt::Decl(Decl::Var(var))) => Some(var),
+            ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => match &export.decl {
+                Decl::Var(var) => Some(var),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(var) = var {
+            for decl in &var.decls {
+                if let (Pat::Ident(ident), Some(init)) = (&decl.name, &decl.init) {
+                    consts.insert(ident.id.sym.to_string(), &**init);
+                }
+            }
+        }
+
+        if let ModuleItem::Stmt(Stmt::Expr(stmt)) = item
+            && let Expr::Assign(assign) = &*stmt.expr
+            && let AssignTarget::Simple(SimpleAssignTarget::Member(member)) = &assign.left
+            && let (Expr::Ident(obj), MemberProp::Ident(prop)) = (&*member.obj, &member.prop)
+        {
+            assigned.insert((obj.sym.to_string(), prop.sym.to_string()), &*assign.right);
+        }
+    }
+
+    for item in &parsed.module.body {
+        match item {
+            ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultExpr(ExportDefaultExpr {
+                expr,
+                ..
+            })) => {
+                let meta = match unwrap_expr(expr) {
+                    Expr::Ident(ident) => consts.get(&*ident.sym).map(|e| unwrap_expr(e)),
+                    other => Some(other),
+                };
+                if let Some(Expr::Object(meta)) = meta {
+                    csf.title = object_field(meta, "title").and_then(string_value);
+                    csf.component =
+                        object_field(meta, "component").and_then(|e| match unwrap_expr(e) {
+                            Expr::Ident(ident) => Some(ident.sym.to_string()),
+                            _ => None,
+                        });
+                    csf.arg_types = object_field(meta, "argTypes")
+                        .map(parse_arg_types)
+                        .unwrap_or_default();
+                }
+            }
+            ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => {
+                let Decl::Var(var) = &export.decl else {
+                    continue;
+                };
+                for decl in &var.decls {
+                    let (Pat::Ident(ident), Some(init)) = (&decl.name, &decl.init) else {
+                        continue;
+                    };
+                    let export_name = ident.id.sym.to_string();
+                    let key = |field: &str| (export_name.clone(), field.to_string());
+
+                    let story_object = match unwrap_expr(init) {
+                        Expr::Object(object) => Some(object),
+                        _ => None,
+                    };
+
+                    let args = story_object
+                        .and_then(|o| object_field(o, "args"))
+                        .or(assigned.get(&key("args")).copied())
+                        .map(object_entries)
+                        .unwrap_or_default();
+
+                    let name = story_object
+                        .and_then(|o| object_field(o, "name"))
+                        .or(assigned.get(&key("storyName")).copied())
+                        .and_then(string_value)
+                        .unwrap_or(export_name.clone());
+
+                    csf.stories.push(CsfStory {
+                        export_name,
+                        name,
+                        args,
+                        line: parsed.line_of(export.span),
+                        code: parsed.snippet(export.span),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(csf)
+}
+
+fn parse_arg_types(expr: &Expr) -> Vec<CsfArgType> {
+    let Expr::Object(object) = unwrap_expr(expr) else {
+        return Vec::new();
+    };
+
+    let mut arg_types = Vec::new();
+    for prop in &object.props {
+        let PropOrSpread::Prop(prop) = prop else {
+            continue;
+        };
+        let Prop::KeyValue(kv) = &**prop else {
+            continue;
+        };
+        let (Some(name), Expr::Object(config)) = (prop_name(&kv.key), unwrap_expr(&kv.value))
+        else {
+            continue;
+        };
+
+        let mut arg_type = CsfArgType {
+            name,
+            description: object_field(config, "description").and_then(string_value),
+            ..Default::default()
+        };
+
+        // `control: "select"` or `control: { type: "select" }`
+        arg_type.control = object_field(config, "control").and_then(|c| match unwrap_expr(c) {
+            Expr::Object(control) => object_field(control, "type").and_then(string_value),
+            other => string_value(other),
+        });
+
+        if let Some(Expr::Array(options)) = object_field(config, "options").map(unwrap_expr) {
+            arg_type.options = options
+                .elems
+                .iter()
+                .flatten()
+                .filter_map(|e| literal_value(unwrap_expr(&e.expr)))
+                .collect();
+        }
+
+        arg_type.default_value = object_field(config, "defaultValue")
+            .and_then(|v| literal_value(unwrap_expr(v)))
+            .or_else(|| {
+                // table: { defaultValue: { summary: "md" } }
+                let Expr::Object(table) = unwrap_expr(object_field(config, "table")?) else {
+                    return None;
+                };
+                let Expr::Object(default) = unwrap_expr(object_field(table, "defaultValue")?)
+                else {
+                    return None;
+                };
+                object_field(default, "summary").and_then(string_value)
+            });
+
+        match object_field(config, "type").map(unwrap_expr) {
+            Some(Expr::Object(type_obj)) => {
+                arg_type.type_name = object_field(type_obj, "name").and_then(string_value);
+                arg_type.required = matches!(
+                    object_field(type_obj, "required").map(unwrap_expr),
+                    Some(Expr::Lit(Lit::Bool(b))) if b.value
+                );
+            }
+            Some(other) => arg_type.type_name = string_value(other),
+            None => {}
+        }
+
+        arg_types.push(arg_type);
+    }
+
+    arg_types
+}
+
+/// The CSF files among the repository files (path, code), parsed in parallel
+pub fn story_files(files: &[(String, String)]) -> Vec<(String, CsfFile)> {
+    files
+        .par_iter()
+        .filter(|(file, _)| is_story_file(file))
+        .filter_map(|(file, code)| Some((file.clone(), parse_csf(code)?)))
+        .collect()
+}
+
+/// Component a CSF file documents : its `component` field, or the file name
+/// (`Button.stories.tsx`) when the meta does not say
+pub fn csf_component_name(file: &str, csf: &CsfFile) -> Option<String> {
+    match &csf.component {
+        Some(component) => Some(component.clone()),
+        None => file
+            .rsplit('/')
+            .next()
+            .and_then(|f| f.split('.').next())
+            .map(str::to_string),
+    }
+}
+
+/// The CSF files documenting a component
+pub fn component_csf<'a>(
+    csf_files: &'a [(String, CsfFile)],
+    component_name: &str,
+) -> Vec<&'a (String, CsfFile)> {
+    csf_files
+        .iter()
+        .filter(|(file, csf)| csf_component_name(file, csf).as_deref() == Some(component_name))
+        .collect()
+}
+
+/// Let the `argTypes` complete the props read from the typescript type :
+/// their description wins, controls and options are added, and the args only
+/// documented in storybook are appended
+pub fn enrich_props(props: &mut Vec<PropInfo>, arg_types: &[CsfArgType]) {
+    for arg_type in arg_types {
+        match props.iter_mut().find(|p| p.name == arg_type.name) {
+            Some(prop) => {
+                if arg_type.description.is_some() {
+                    prop.description = arg_type.description.clone();
+                }
+                if arg_type.control.is_some() {
+                    prop.control = arg_type.control.clone();
+                }
+                if !arg_type.options.is_empty() {
+                    prop.options = arg_type.options.clone();
+                }
+                if arg_type.default_value.is_some() {
+                    prop.default_value = arg_type.default_value.clone();
+                }
+            }
+            None => props.push(PropInfo {
+                name: arg_type.name.clone(),
+                prop_type: match (&arg_type.type_name, arg_type.options.is_empty()) {
+                    (Some(type_name), _) => type_name.clone(),
+                    (None, false) => arg_type.options.join(" | "),
+                    (None, true) => "unknown".to_string(),
+                },
+                optional: !arg_type.required,
+                description: arg_type.description.clone(),
+                control: arg_type.control.clone(),
+                options: arg_type.options.clone(),
+                default_value: arg_type.default_value.clone(),
+            }),
+        }
+    }
+}
+
+/// Storybook id of a story, `Forms/Button` + `PrimaryLarge` gives
+/// `forms-button--primary-large`
+pub fn story_id(title: &str, export_name: &str) -> String {
+    format!(
+        "{}--{}",
+        sanitize_id(title),
+        sanitize_id(&split_camel(export_name))
+    )
+}
+
+fn split_camel(name: &str) -> String {
+    let mut out = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() && previous_lower {
+            out.push(' ');
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        out.push(c);
+    }
+    out
+}
+
+fn sanitize_id(value: &str) -> String {
+    value
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Look through `as Meta`, `satisfies Meta`, `as const` and parentheses
+pub fn unwrap_expr(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::TsAs(e) => unwrap_expr(&e.expr),
+        Expr::TsSatisfies(e) => unwrap_expr(&e.expr),
+        Expr::TsConstAssertion(e) => unwrap_expr(&e.expr),
+        Expr::TsTypeAssertion(e) => unwrap_expr(&e.expr),
+        Expr::Paren(e) => unwrap_expr(&e.expr),
+        other => other,
+    }
+}
+
+pub fn object_field<'a>(object: &'a ObjectLit, field: &str) -> Option<&'a Expr> {
+    object.props.iter().find_map(|prop| match prop {
+        PropOrSpread::Prop(prop) => match &**prop {
+            Prop::KeyValue(kv) if prop_name(&kv.key).as_deref() == Some(field) => Some(&*kv.value),
+            _ => None,
+        },
+        PropOrSpread::Spread(_) => None,
+    })
+}
+
+/// `{ label: "Save", disabled }` -> [label = "Save", disabled = None]
+pub fn object_entries(expr: &Expr) -> Vec<JsxProp> {
+    let Expr::Object(object) = unwrap_expr(expr) else {
+        return Vec::new();
+    };
+
+    object
+        .props
+        .iter()
+        .filter_map(|prop| match prop {
+            PropOrSpread::Prop(prop) => match &**prop {
+                Prop::KeyValue(kv) => Some(JsxProp {
+                    name: prop_name(&kv.key)?,
+                    value: literal_value(unwrap_expr(&kv.value)),
+                }),
+                Prop::Shorthand(ident) => Some(JsxProp {
+                    name: ident.sym.to_string(),
+                    value: None,
+                }),
+                Prop::Method(method) => Some(JsxProp {
+                    name: prop_name(&method.key)?,
+                    value: None,
+                }),
+                _ => None,
+            },
+            PropOrSpread::Spread(_) => None,
+        })
+        .collect()
+}
+
+pub fn prop_name(key: &PropName) -> Option<String> {
+    match key {
+        PropName::Ident(ident) => Some(ident.sym.to_string()),
+        PropName::Str(s) => Some(s.value.to_string()),
+        PropName::Num(n) => Some(n.value.to_string()),
+        _ => None,
+    }
+}
+
+pub fn string_value(expr: &Expr) -> Option<String> {
+    match unwrap_expr(expr) {
+        Expr::Lit(Lit::Str(s)) => Some(s.value.to_string()),
+        Expr::Tpl(tpl) if tpl.exprs.is_empty() => tpl.quasis.first().map(|q| q.raw.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSF3: &str = r#"import type { Meta, StoryObj } from "@storybook/react";
+import { Button } from "./Button";
+
+const meta = {
+  title: "Forms/Button",
+  component: Button,
+  argTypes: {
+    size: {
+      description: "How big",
+      control: { type: "select" },
+      options: ["sm", "md"],
+      table: { defaultValue: { summary: "md" } },
+    },
+    tone: { control: "radio", type: { name: "string", required: true } },
+  },
+} satisfies Meta<typeof Button>;
+export default meta;
+
+export const PrimaryLarge: StoryObj<typeof meta> = {
+  name: "Primary large",
+  args: { label: "Save", size: "md", disabled: true },
+};
+"#;
+
+    #[test]
+    fn reads_the_meta_and_the_stories_of_csf3() {
+        let csf = parse_csf(CSF3).unwrap();
+        assert_eq!(csf.title.as_deref(), Some("Forms/Button"));
+        assert_eq!(csf.component.as_deref(), Some("Button"));
+
+        let size = &csf.arg_types[0];
+        assert_eq!(size.description.as_deref(), Some("How big"));
+        assert_eq!(size.control.as_deref(), Some("select"));
+        assert_eq!(size.options, [r#""sm""#, r#""md""#]);
+        assert_eq!(size.default_value.as_deref(), Some("md"));
+        let tone = &csf.arg_types[1];
+        assert_eq!(tone.control.as_deref(), Some("radio"));
+        assert_eq!(tone.type_name.as_deref(), Some("string"));
+        assert!(tone.required);
+
+        assert_eq!(csf.stories.len(), 1);
+        let story = &csf.stories[0];
+        assert_eq!(story.export_name, "PrimaryLarge");
+        assert_eq!(story.name, "Primary large");
+        assert_eq!(story.line, 19);
+        let args: Vec<&str> = story.args.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(args, ["label", "size", "disabled"]);
+    }
+
+    #[test]
+    fn reads_the_assigned_args_of_csf2() {
+        let code = r#"export default { title: "Card" };
+const Template = (args) => <Card {...args} />;
+export const Basic = Template.bind({});
+Basic.args = { title: "Hello" };
+Basic.storyName = "The basics";
+"#;
+        let csf = parse_csf(code).unwrap();
+        assert_eq!(csf.component, None);
+        assert_eq!(
+            csf_component_name("src/Card.stories.tsx", &csf).as_deref(),
+            Some("Card")
+        );
+        assert_eq!(csf.stories[0].name, "The basics");
+        assert_eq!(csf.stories[0].args[0].value.as_deref(), Some(r#""Hello""#));
+
+        assert!(parse_csf("export const = ;").is_none());
+    }
+
+    #[test]
+    fn completes_the_props_with_the_arg_types() {
+        let csf = parse_csf(CSF3).unwrap();
+        let mut props = vec![PropInfo {
+            name: "size".to_string(),
+            prop_type: r#""sm" | "md""#.to_string(),
+            optional: true,
+            description: Some("from the type".to_string()),
+            control: None,
+            options: Vec::new(),
+            default_value: None,
+        }];
+        enrich_props(&mut props, &csf.arg_types);
+
+        assert_eq!(props[0].description.as_deref(), Some("How big"));
+        assert_eq!(props[0].default_value.as_deref(), Some("md"));
+        assert_eq!(props[1].name, "tone");
+        assert_eq!(props[1].prop_type, "string");
+        assert!(!props[1].optional);
+    }
+
+    #[test]
+    fn builds_the_storybook_ids() {
+        assert_eq!(
+            story_id("Forms/Button", "PrimaryLarge"),
+            "forms-button--primary-large"
+        );
+        assert_eq!(
+            story_id("Design System/Icon 2", "Size24"),
+            "design-system-icon-2--size24"
+        );
+        assert!(is_story_file("a/Button.stories.tsx"));
+        assert!(!is_story_file("a/Button.tsx"));
+    }
+}
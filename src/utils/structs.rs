@@ -1,21 +1,273 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
+use crate::utils::search::{SearchDocument, SearchIndex};
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ComponentModel {
     pub name: String,
     pub type_name: String,
     pub comp_type: String,
     pub comp_code: String,
+    pub props: Vec<PropInfo>,
     pub is_legacy: bool,
+    pub legacy: Option<LegacyInfo>,
+    pub meta: ComponentMeta,
+    pub styles: Vec<StyleSheet>,
+    pub assets: Vec<StaticAsset>,
+    // v3 source map of `comp_code` back to the repository files
+    pub source_map: Option<serde_json::Value>,
+    // third-party packages needed by the component and what it merges
+    pub dependencies: Vec<NpmDependency>,
+    // stories, tests and call sites showing the component in use
+    pub examples: Vec<UsageExample>,
+    // limits hit while collecting, the assets concerned are left as urls
+    pub diagnostics: Vec<LimitDiagnostic>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageExample {
+    // story | test | call_site
+    pub kind: String,
+    pub title: Option<String>,
+    // path relative to the repository root
+    pub file: String,
+    pub line: usize,
+    pub code: String,
+    // props (or story args) used in the example
+    pub props: Vec<ExampleProp>,
+    // `{...rest}` was passed, more props than listed may be set
+    pub has_spread: bool,
+    // storybook id (`forms-button--primary`) of the story examples
+    pub story_id: Option<String>,
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExampleProp {
+    pub name: String,
+    // literal value as written, None when dynamic
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentUsages {
+    pub component: String,
+    // number of JSX usages
+    pub total: usize,
+    // number of files using the component
+    pub files: usize,
+    // usages passing `{...rest}`, their props can not be known
+    pub with_spread: usize,
+    pub prop_stats: Vec<PropUsageStat>,
+    // declared props never passed explicitly
+    pub unused_props: Vec<String>,
+    pub usages: Vec<ComponentUsage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentUsage {
+    // story | test | call_site
+    pub kind: String,
+    pub file: String,
+    pub line: usize,
+    pub props: Vec<ExampleProp>,
+    pub has_spread: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PropUsageStat {
+    pub name: String,
+    pub count: usize,
+    // times the value was not a literal
+    pub dynamic: usize,
+    // literal values passed, most frequent first
+    pub values: Vec<PropValueCount>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PropValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DeadCodeReport {
+    pub unused_components: Vec<UnusedComponent>,
+    pub dead_props: Vec<DeadProp>,
+}
+
+impl DeadCodeReport {
+    pub fn is_empty(&self) -> bool {
+        self.unused_components.is_empty() && self.dead_props.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnusedComponent {
+    pub file: String,
+    pub name: String,
+    // stories and tests importing it, they do not count as uses
+    pub documented_by: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeadProp {
+    pub file: String,
+    pub component: String,
+    pub prop: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum SemverLevel {
+    None,
+    Patch,
+    Minor,
+    Major,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiDiffReport {
+    pub from: String,
+    pub to: String,
+    pub from_commit: String,
+    pub to_commit: String,
+    // highest level of all the components, the release to make
+    pub level: SemverLevel,
+    pub components: Vec<ComponentApiDiff>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentApiDiff {
+    pub component: String,
+    pub file: String,
+    pub level: SemverLevel,
+    pub changes: Vec<ApiChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiChange {
+    // prop_removed | prop_required | union_member_removed | component_renamed ...
+    pub kind: String,
+    pub prop: Option<String>,
+    pub detail: String,
+    pub level: SemverLevel,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CompareQuery {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub commit: String,
+    pub author: String,
+    // unix time of the commit
+    pub timestamp: i64,
+    pub subject: String,
+    // files of the component (or of its merged dependencies) it touched
+    pub files: Vec<String>,
+    // only a merged dependency changed
+    pub via_dependency: bool,
+    pub level: SemverLevel,
+    pub props_changes: Vec<ApiChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryQuery {
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NpmDependency {
+    pub name: String,
+    // range declared in package.json
+    pub requested: Option<String>,
+    // version installed according to the lockfile
+    pub resolved: Option<String>,
+    // dependencies | peerDependencies | devDependencies
+    pub dependency_type: Option<String>,
+    // repository files importing the package
+    pub imported_by: Vec<String>,
+    pub heavy: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PropInfo {
+    pub name: String,
+    pub prop_type: String,
+    pub optional: bool,
+    // JSDoc written above the prop (or the storybook argTypes description)
+    pub description: Option<String>,
+    // storybook control, options and default from the argTypes
+    pub control: Option<String>,
+    pub options: Vec<String>,
+    pub default_value: Option<String>,
+}
+
+/// What `//<legacy ...>` or `@deprecated` says about a legacy component
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LegacyInfo {
+    pub reason: Option<String>,
+    // the component to migrate to
+    pub replacement: Option<String>,
+    pub since: Option<String>,
+    pub remove_in: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StyleSheet {
+    // path relative to the repository root
+    pub path: String,
+    // css | scss | sass | less, suffixed with "-module" for css modules
+    pub kind: String,
+    // the identifier the stylesheet is imported as (`styles` in `import styles from ...`)
+    pub binding: Option<String>,
+    pub content: String,
+    // original class name -> generated class name (css modules only)
+    pub class_map: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StaticAsset {
+    pub path: String,
+    pub binding: Option<String>,
+    pub mime: String,
+    pub data_uri: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentQuery {
+    // inline the collected images and fonts as data URIs
+    pub inline_assets: Option<bool>,
+}
+
+/// The repository served, saved in /etc/compo-doc/config/config
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RepoConfig {
+    pub url: String,
+    pub branch: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ConfigContent {
     pub repo: String,
     pub branch: String,
+    // only validate and check the repository is reachable, nothing is saved
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleQuery {
+    // CDN or import-map base used for the bare npm imports
+    pub cdn: Option<String>,
 }
 
 pub type SharedState = Arc<RwLock<AppState>>;
@@ -24,12 +276,270 @@ pub type SharedState = Arc<RwLock<AppState>>;
 pub struct AppState {
     pub comp_liste: Vec<ComponentsList>,
     pub curr_ver: String,
+    pub search_index: Arc<SearchIndex>,
+    // limits hit by the last sync, the files concerned were skipped
+    pub diagnostics: Vec<LimitDiagnostic>,
+    // component -> props, completed by the storybook argTypes
+    pub props: HashMap<String, Vec<PropInfo>>,
+    pub examples: HashMap<String, Vec<UsageExample>>,
+}
+
+/// Everything a sync built for a commit, saved so a restart serves it at once
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StoredIndex {
+    // STORED_INDEX_VERSION when saved, the other versions are rebuilt
+    pub version: u32,
+    pub repo: String,
+    pub commit: String,
+    // unix time of the sync
+    pub synced_at: i64,
+    pub components: Vec<ComponentsList>,
+    // component -> props of its type, completed by the storybook argTypes
+    pub props: HashMap<String, Vec<PropInfo>>,
+    // component -> repository components it imports
+    pub dependencies: HashMap<String, Vec<String>>,
+    pub examples: HashMap<String, Vec<UsageExample>>,
+    pub documents: Vec<SearchDocument>,
+    pub diagnostics: Vec<LimitDiagnostic>,
+}
+
+/// Merged code of a component at a commit, saved beside the index so a
+/// restart does not merge it again
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StoredMerge {
+    // STORED_INDEX_VERSION when saved, the other versions are merged again
+    pub version: u32,
+    pub code: String,
+    // files inlined by the merge, the only ones the source map may point to
+    pub sources: Vec<String>,
+}
+
+/// One line of the audit log
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEvent {
+    // unix time
+    pub timestamp: i64,
+    // config_changed | sync
+    pub event: String,
+    // api key name or token subject
+    pub actor: String,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    // commit pulled by a sync
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    // false when the sync found the branch unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(event: &str, actor: &str, success: bool) -> Self {
+        AuditEvent {
+            timestamp: crate::utils::audit::now_unix(),
+            event: event.to_string(),
+            actor: actor.to_string(),
+            success,
+            repo: None,
+            branch: None,
+            commit: None,
+            duration_ms: None,
+            changed: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AuditQuery {
+    // unix time range, both ends included
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub event: Option<String>,
+    pub actor: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SyncStatus {
+    // commit the index was built from
+    pub commit: String,
+    pub components: usize,
+    // false when the branch had not moved
+    pub changed: bool,
+    pub diagnostics: Vec<LimitDiagnostic>,
+    // time spent indexing, None when the branch had not moved
+    pub duration_ms: Option<u64>,
+}
+
+/// Time spent indexing one component file
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileTiming {
+    pub file: String,
+    pub duration_ms: f64,
+}
+
+/// State of the sync pipeline, the timings are the ones of the last sync
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SyncProgress {
+    pub running: bool,
+    // reading | stories | components | committing | done
+    pub phase: String,
+    // component files to index and indexed so far
+    pub total: usize,
+    pub done: usize,
+    // unix time
+    pub started_at: Option<i64>,
+    pub duration_ms: Option<u64>,
+    // slowest first
+    pub timings: Vec<FileTiming>,
+}
+
+/// A resource limit a repository went over
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LimitDiagnostic {
+    // name of the setting in limits.json
+    pub limit: String,
+    // file, component or repository concerned
+    pub subject: String,
+    pub value: u64,
+    pub max: u64,
+}
+
+impl LimitDiagnostic {
+    /// Components importing each other, `cycle` starts and ends on the same one
+    pub fn merge_cycle(cycle: &[String]) -> Self {
+        LimitDiagnostic {
+            limit: "merge_cycle".to_string(),
+            subject: cycle.join(" -> "),
+            value: cycle.len().saturating_sub(1) as u64,
+            max: 0,
+        }
+    }
+
+    /// A file missing, unreadable or resolving outside of the repository
+    pub fn unreadable_file(path: &str) -> Self {
+        LimitDiagnostic {
+            limit: "unreadable_file".to_string(),
+            subject: path.to_string(),
+            value: 0,
+            max: 0,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self.limit.as_str() {
+            "merge_cycle" => format!("{} : import cycle, it can not be merged", self.subject),
+            "unreadable_file" => format!("{} : could not be read", self.subject),
+            _ => format!(
+                "{} : {} exceeded ({} > {})",
+                self.subject, self.limit, self.value, self.max
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ComponentsList {
     pub name: String,
     pub is_legacy: bool,
+    pub legacy: Option<LegacyInfo>,
+    pub meta: ComponentMeta,
+    // CSF files documenting the component
+    pub storybook: Vec<StorybookInfo>,
+    // unix time of the last commit touching the file
+    pub updated_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StorybookInfo {
+    pub file: String,
+    pub title: Option<String>,
+    // story names
+    pub stories: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Experimental,
+    Stable,
+    Legacy,
+}
+
+impl ComponentStatus {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "experimental" => Some(ComponentStatus::Experimental),
+            "stable" => Some(ComponentStatus::Stable),
+            "legacy" | "deprecated" => Some(ComponentStatus::Legacy),
+            _ => None,
+        }
+    }
+}
+
+/// What `//<meta ...>` or the component JSDoc declares
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ComponentMeta {
+    pub category: Option<String>,
+    pub status: Option<ComponentStatus>,
+    // owning team
+    pub owner: Option<String>,
+    pub keywords: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentGroup {
+    pub category: String,
+    pub components: Vec<ComponentsList>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ListQuery {
+    // "category" to group the sidebar
+    pub group_by: Option<String>,
+    pub status: Option<String>,
+    pub owner: Option<String>,
+    pub legacy: Option<bool>,
+    pub category: Option<String>,
+    // case insensitive name prefix
+    pub q: Option<String>,
+    // name | updated
+    pub sort: Option<String>,
+    // 1 based
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchHit {
+    pub component: String,
+    pub score: f64,
+    // name | meta | props | docs | code
+    pub matched_fields: Vec<String>,
+    pub snippet: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Pagination {
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub total_pages: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,6 +547,17 @@ pub struct JsonResponse<T> {
     pub success: bool,
     pub message: Option<String>,
     pub result: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<Pagination>,
+    // invalid fields of the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
 }
 
 impl<T> JsonResponse<T> {
@@ -45,6 +566,8 @@ impl<T> JsonResponse<T> {
             success: false,
             message: Some(error),
             result: None,
+            pagination: None,
+            errors: None,
         };
     }
     pub fn make_success(message: &str, result: T) -> Self {
@@ -52,6 +575,16 @@ impl<T> JsonResponse<T> {
             success: true,
             message: Some(message.to_string()),
             result: Some(result),
+            pagination: None,
+            errors: None,
         };
     }
+    pub fn with_pagination(mut self, pagination: Pagination) -> Self {
+        self.pagination = Some(pagination);
+        self
+    }
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = Some(errors);
+        self
+    }
 }
@@ -0,0 +1,444 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use regex::Regex;
+
+use crate::utils::{
+    code_merge::{component_rel_path, get_imported_components},
+    limits::check_file_size,
+    structs::{LimitDiagnostic, StaticAsset, StyleSheet},
+    utils::{normalize_path, read_repo_bytes, read_repo_file, repo_file_size},
+};
+
+/// Stylesheets and static assets used by a component and every component
+/// merged into it
+#[derive(Debug, Default)]
+pub struct CollectedStyles {
+    pub styles: Vec<StyleSheet>,
+    pub assets: Vec<StaticAsset>,
+    // assets too large to inline
+    pub diagnostics: Vec<LimitDiagnostic>,
+    seen: HashSet<String>,
+}
+
+pub fn collect_component_styles(tsx: &str, repo: &str, inline_assets: bool) -> CollectedStyles {
+    let mut collected = CollectedStyles::default();
+    let mut visited = HashSet::new();
+
+    walk_component(tsx, repo, inline_assets, &mut collected, &mut visited);
+
+    collected
+}
+
+fn walk_component(
+    tsx: &str,
+    repo: &str,
+    inline_assets: bool,
+    collected: &mut CollectedStyles,
+    visited: &mut HashSet<String>,
+) {
+    let repo_root = format!("/etc/compo-doc/tmp/{repo}");
+
+    collect_from_source(tsx, &repo_root, "components", inline_assets, collected);
+
+    // follow the same components the merger inlines
+    for component in get_imported_components(tsx) {
+        if !visited.insert(component.clone()) {
+            continue;
+        }
+
+        if let Ok(child) = read_repo_file(&repo_root, &component_rel_path(&component)) {
+            walk_component(&child, repo, inline_assets, collected, visited);
+        }
+    }
+}
+
+/// Collect the style and asset imports of a single source file,
+/// `file_dir` is the directory of the file relative to `repo_root`
+pub fn collect_from_source(
+    tsx: &str,
+    repo_root: &str,
+    file_dir: &str,
+    inline_assets: bool,
+    collected: &mut CollectedStyles,
+) {
+    // import x from "spec" | import "spec"
+    let import_re =
+        Regex::new(r#"(?m)^\s*import\s+(?:([^;]+?)\s+from\s+)?["']([^"']+)["'];?\s*$"#).unwrap();
+    let style_re = Regex::new(r#"\.(css|scss|sass|less)$"#).unwrap();
+    let asset_re = Regex::new(r#"\.(svg|png|jpe?g|gif|webp|bmp|ico|ttf|otf|woff2?)$"#).unwrap();
+
+    for cap in import_re.captures_iter(tsx) {
+        let binding = cap.get(1).map(|m| m.as_str().trim().to_string());
+        let spec = &cap[2];
+
+        // only repo-local files can be collected
+        if !spec.starts_with('.') {
+            continue;
+        }
+
+        let rel_path = normalize_path(&format!("{file_dir}/{spec}"));
+
+        if style_re.is_match(spec) {
+            if !collected.seen.insert(rel_path.clone()) {
+                continue;
+            }
+            match read_repo_file(repo_root, &rel_path) {
+                Ok(content) => {
+                    let sheet = build_style_sheet(
+                        &rel_path,
+                        binding,
+                        content,
+                        repo_root,
+                        inline_assets,
+                        collected,
+                    );
+                    collected.styles.push(sheet);
+                }
+                Err(err) => println!("could not read stylesheet {rel_path} : {err}"),
+            }
+        } else if asset_re.is_match(spec) {
+            push_asset(&rel_path, binding, repo_root, inline_assets, collected);
+        }
+    }
+}
+
+fn build_style_sheet(
+    rel_path: &str,
+    binding: Option<String>,
+    content: String,
+    repo_root: &str,
+    inline_assets: bool,
+    collected: &mut CollectedStyles,
+) -> StyleSheet {
+    let extension = rel_path.rsplit('.').next().unwrap_or("css").to_string();
+    let is_module = rel_path.contains(".module.");
+    let sheet_dir = parent_dir(rel_path);
+
+    // the stylesheet urls point to assets too
+    let url_re = Regex::new(r#"url\(\s*["']?([^"')]+)["']?\s*\)"#).unwrap();
+    let mut content = content;
+    for cap in url_re.captures_iter(&content.clone()) {
+        let url = &cap[1];
+        if !url.starts_with('.') {
+            continue;
+        }
+        let asset_path = normalize_path(&format!("{sheet_dir}/{url}"));
+        if let Some(data_uri) = push_asset(&asset_path, None, repo_root, inline_assets, collected) {
+            content = content.replace(&cap[0], &format!("url(\"{data_uri}\")"));
+        }
+    }
+
+    // scss / sass / less are passed through as-is, the class names are kept
+    let (content, class_map) = match (is_module, extension.as_str()) {
+        (true, "css") => {
+            let (scoped, map) = scope_css_module(rel_path, &content);
+            (scoped, Some(map))
+        }
+        (true, _) => {
+            let map = css_class_names(&content)
+                .into_iter()
+                .map(|c| (c.clone(), c))
+                .collect();
+            (content, Some(map))
+        }
+        _ => (content, None),
+    };
+
+    StyleSheet {
+        path: rel_path.to_string(),
+        kind: if is_module {
+            format!("{extension}-module")
+        } else {
+            extension
+        },
+        binding,
+        content,
+        class_map,
+    }
+}
+
+/// Register an asset and return its data URI when inlining is requested
+fn push_asset(
+    rel_path: &str,
+    binding: Option<String>,
+    repo_root: &str,
+    inline_assets: bool,
+    collected: &mut CollectedStyles,
+) -> Option<String> {
+    // read once, the other stylesheets using it get the same data URI
+    if !collected.seen.insert(rel_path.to_string()) {
+        return collected
+            .assets
+            .iter()
+            .find(|asset| asset.path == rel_path)
+            .and_then(|asset| asset.data_uri.clone());
+    }
+
+    let mime = mime_from_path(rel_path);
+    let data_uri = if inline_assets {
+        inline_asset(rel_path, mime, repo_root, &mut collected.diagnostics)
+    } else {
+        None
+    };
+
+    collected.assets.push(StaticAsset {
+        path: rel_path.to_string(),
+        binding,
+        mime: mime.to_string(),
+        data_uri: data_uri.clone(),
+    });
+
+    data_uri
+}
+
+/// Data URI of an asset, `None` keeps it as a url when it cannot be read or
+/// is over `max_file_size`
+fn inline_asset(
+    rel_path: &str,
+    mime: &str,
+    repo_root: &str,
+    diagnostics: &mut Vec<LimitDiagnostic>,
+) -> Option<String> {
+    // a symlink to a file of the server is refused like a missing file
+    let read = repo_file_size(repo_root, rel_path).and_then(|size| {
+        if let Err(diagnostic) = check_file_size(rel_path, size as usize) {
+            diagnostics.push(diagnostic);
+            return Ok(None);
+        }
+        read_repo_bytes(repo_root, rel_path).map(Some)
+    });
+
+    match read {
+        Ok(bytes) => bytes.map(|bytes| format!("data:{mime};base64,{}", STANDARD.encode(bytes))),
+        Err(err) => {
+            println!("could not read asset {rel_path} : {err}");
+            None
+        }
+    }
+}
+
+/// Rename every class of a css module the way css-loader does
+/// (`[name]_[local]__[hash]`) and return the rewritten css with its mapping
+pub fn scope_css_module(rel_path: &str, css: &str) -> (String, HashMap<String, String>) {
+    let stem = Path::new(rel_path)
+        .file_name()
+        .and_then(|f| f.to_str())
+        .and_then(|f| f.split('.').next())
+        .unwrap_or("module")
+        .to_string();
+
+    let mut class_map = HashMap::new();
+
+    let scoped = class_selector_re()
+        .replace_all(css, |caps: &regex::Captures<'_>| match caps.name("class") {
+            Some(class) => {
+                let local = class.as_str();
+                let scoped = class_map
+                    .entry(local.to_string())
+                    .or_insert_with(|| {
+                        format!(
+                            "{stem}_{local}__{}",
+                            short_hash(&format!("{rel_path}:{local}"))
+                        )
+                    })
+                    .clone();
+                format!(".{scoped}")
+            }
+            None => caps[0].to_string(),
+        })
+        .to_string();
+
+    (scoped, class_map)
+}
+
+pub fn css_class_names(css: &str) -> Vec<String> {
+    let mut names: Vec<String> = class_selector_re()
+        .captures_iter(css)
+        .filter_map(|caps| caps.name("class").map(|m| m.as_str().to_string()))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    names.sort();
+    names
+}
+
+// comments, strings and urls are matched first so their dots are never
+// mistaken for class selectors
+fn class_selector_re() -> Regex {
+    Regex::new(
+        r#"(?s)/\*.*?\*/|"[^"]*"|'[^']*'|url\([^)]*\)|\.(?P<class>-?[_a-zA-Z][_a-zA-Z0-9-]*)"#,
+    )
+    .unwrap()
+}
+
+fn short_hash(input: &str) -> String {
+    // FNV-1a, stable across runs unlike the std hasher
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in input.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    format!("{:05x}", hash & 0xfffff)
+}
+
+fn parent_dir(rel_path: &str) -> String {
+    match rel_path.rsplit_once('/') {
+        Some((dir, _)) => dir.to_string(),
+        None => String::new(),
+    }
+}
+
+pub fn mime_from_path(path: &str) -> &'static str {
+    match path
+        .rsplit('.')
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+        .as_str()
+    {
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "css" => "text/css",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_clone(name: &str) -> (std::path::PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("compo-doc-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("repo/components")).unwrap();
+        std::fs::write(dir.join("repo/components/icon.svg"), "<svg/>").unwrap();
+        std::fs::write(dir.join("shadow"), "root:x:0:0").unwrap();
+        let root = dir.join("repo").to_string_lossy().to_string();
+        (dir, root)
+    }
+
+    #[test]
+    fn inlines_the_assets_of_the_clone() {
+        let (dir, root) = fake_clone("assets");
+        let mut collected = CollectedStyles::default();
+        collect_from_source(
+            "import icon from \"./icon.svg\";",
+            &root,
+            "components",
+            true,
+            &mut collected,
+        );
+
+        assert_eq!(collected.assets.len(), 1);
+        assert_eq!(
+            collected.assets[0].data_uri.as_deref(),
+            Some("data:image/svg+xml;base64,PHN2Zy8+")
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn never_inlines_a_symlink_leaving_the_clone() {
+        let (dir, root) = fake_clone("asset-symlink");
+        std::os::unix::fs::symlink(dir.join("shadow"), dir.join("repo/components/logo.png"))
+            .unwrap();
+        std::fs::write(
+            dir.join("repo/components/card.css"),
+            ".card { background: url(./logo.png); }",
+        )
+        .unwrap();
+
+        let mut collected = CollectedStyles::default();
+        collect_from_source(
+            "import logo from \"./logo.png\";\nimport \"./card.css\";",
+            &root,
+            "components",
+            true,
+            &mut collected,
+        );
+
+        assert!(collected.assets.iter().all(|a| a.data_uri.is_none()));
+        assert!(!collected.styles[0].content.contains("base64"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn keeps_an_oversized_asset_as_a_url() {
+        let (dir, root) = fake_clone("asset-oversized");
+        let size = crate::utils::limits::limits().max_file_size as usize + 1;
+        std::fs::write(dir.join("repo/components/hero.png"), vec![0u8; size]).unwrap();
+
+        let mut collected = CollectedStyles::default();
+        collect_from_source(
+            "import hero from \"./hero.png\";",
+            &root,
+            "components",
+            true,
+            &mut collected,
+        );
+
+        assert_eq!(collected.assets.len(), 1);
+        assert_eq!(collected.assets[0].data_uri, None);
+        assert_eq!(collected.diagnostics.len(), 1);
+        assert_eq!(collected.diagnostics[0].limit, "max_file_size");
+        assert_eq!(collected.diagnostics[0].subject, "components/hero.png");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn inlines_an_asset_used_twice_once() {
+        let (dir, root) = fake_clone("asset-twice");
+        for sheet in ["a.css", "b.css"] {
+            std::fs::write(
+                dir.join("repo/components").join(sheet),
+                ".icon { background: url(./icon.svg); }",
+            )
+            .unwrap();
+        }
+
+        let mut collected = CollectedStyles::default();
+        collect_from_source(
+            "import \"./a.css\";\nimport \"./b.css\";\nimport icon from \"./icon.svg\";",
+            &root,
+            "components",
+            true,
+            &mut collected,
+        );
+
+        assert_eq!(collected.assets.len(), 1);
+        assert!(
+            collected
+                .styles
+                .iter()
+                .all(|sheet| sheet.content.contains("data:image/svg+xml;base64,PHN2Zy8+"))
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn scopes_the_classes_of_a_css_module() {
+        let (css, map) = scope_css_module(
+            "components/Button.module.css",
+            ".primary { color: red } /* .comment */ .icon { background: url(./a.png) }",
+        );
+
+        assert_eq!(map.len(), 2);
+        assert!(map["primary"].starts_with("Button_primary__"));
+        assert!(css.contains(&format!(".{}", map["icon"])));
+        assert!(css.contains("/* .comment */"));
+        assert!(css.contains("url(./a.png)"));
+    }
+}
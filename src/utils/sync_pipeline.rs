@@ -0,0 +1,471 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{LazyLock, Mutex},
+    time::Instant,
+};
+
+use rayon::prelude::*;
+
+use crate::utils::{
+    audit::now_unix,
+    code_merge::{get_imported_components, principal_component_name},
+    examples::{CODE_FILE_EXTENSIONS, component_examples},
+    index_store::STORED_INDEX_VERSION,
+    indexer::{attach_storybook, index_component},
+    limits::check_file_size,
+    search::{SearchDocument, extract_props},
+    storybook::{CsfFile, component_csf, enrich_props, story_files},
+    structs::{
+        ComponentsList, FileTiming, LimitDiagnostic, PropInfo, StoredIndex, SyncProgress,
+        UsageExample,
+    },
+    utils::{last_commit_times, read_repo_file, read_repo_files},
+};
+
+static PROGRESS: LazyLock<Mutex<SyncProgress>> = LazyLock::new(Default::default);
+
+// held for the whole sync, two syncs would clone into the same folder
+pub static SYNC_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+fn update_progress(update: impl FnOnce(&mut SyncProgress)) {
+    let mut progress = PROGRESS.lock().unwrap_or_else(|e| e.into_inner());
+    update(&mut progress);
+}
+
+pub fn sync_progress() -> SyncProgress {
+    PROGRESS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Everything extracted from one component file
+struct IndexedFile {
+    entry: ComponentsList,
+    document: Option<SearchDocument>,
+    props: Vec<PropInfo>,
+    dependencies: Vec<String>,
+    examples: Vec<UsageExample>,
+    diagnostic: Option<LimitDiagnostic>,
+    timing: FileTiming,
+}
+
+/// Index every component file of the clone, the files are parsed once and in
+/// parallel. Nothing touches the state, the caller commits the index at once.
+pub fn build_index(
+    repo: &str,
+    commit: &str,
+    component_files: &[String],
+    link_base: Option<&str>,
+) -> StoredIndex {
+    let empty = StoredIndex {
+        version: STORED_INDEX_VERSION,
+        repo: repo.to_string(),
+        commit: commit.to_string(),
+        synced_at: now_unix(),
+        components: Vec::new(),
+        props: HashMap::new(),
+        dependencies: HashMap::new(),
+        examples: HashMap::new(),
+        documents: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    index_components(empty, commit, component_files, component_files, link_base)
+}
+
+/// Patch the index of the previous commit with the files `changed` since,
+/// only the components they touch and their dependents are parsed again
+pub fn update_index(
+    mut previous: StoredIndex,
+    commit: &str,
+    component_files: &[String],
+    changed: &[String],
+    link_base: Option<&str>,
+) -> StoredIndex {
+    let repo_root = format!("/etc/compo-doc/tmp/{}", previous.repo);
+    let affected = affected_components(&previous, &repo_root, component_files, changed);
+    println!(
+        "{} files changed since {}, re-indexing {} components",
+        changed.len(),
+        previous.commit,
+        affected.len()
+    );
+
+    // the kept examples did not change, their links follow the new commit
+    let old_blob = format!("/blob/{}/", previous.commit);
+    let new_blob = format!("/blob/{commit}/");
+    for example in previous.examples.values_mut().flatten() {
+        if let Some(link) = &mut example.link {
+            *link = link.replace(&old_blob, &new_blob);
+        }
+    }
+
+    index_components(previous, commit, component_files, &affected, link_base)
+}
+
+fn component_stem(file: &str) -> &str {
+    file.strip_suffix(".tsx").unwrap_or(file)
+}
+
+/// Components whose index entry depends on the changed files : the changed
+/// components, the ones taking an example or a story from a changed file,
+/// the ones a changed file mentions, then everything merging them
+fn affected_components(
+    previous: &StoredIndex,
+    repo_root: &str,
+    component_files: &[String],
+    changed: &[String],
+) -> Vec<String> {
+    let indexed: HashSet<&str> = previous
+        .components
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect();
+    // deleted components included, their dependents are merged again
+    let mut touched: HashSet<String> = component_files
+        .iter()
+        .filter(|file| !indexed.contains(file.as_str()))
+        .cloned()
+        .collect();
+
+    for path in changed {
+        if let Some(file) = path.strip_prefix("components/") {
+            touched.insert(file.to_string());
+        }
+
+        for entry in &previous.components {
+            if entry.storybook.iter().any(|story| &story.file == path) {
+                touched.insert(entry.name.clone());
+            }
+        }
+        for (name, examples) in &previous.examples {
+            if examples.iter().any(|example| &example.file == path) {
+                touched.insert(name.clone());
+            }
+        }
+
+        // a new call site or story, the deleted files have no content
+        if let Ok(code) = read_repo_file(repo_root, path) {
+            for file in component_files {
+                if code.contains(component_stem(file)) {
+                    touched.insert(file.clone());
+                }
+            }
+        }
+    }
+
+    // the merged code of a dependent embeds its dependencies
+    let mut pending: Vec<String> = touched.iter().cloned().collect();
+    while let Some(file) = pending.pop() {
+        let stem = component_stem(&file);
+        for (dependent, dependencies) in &previous.dependencies {
+            if dependencies.iter().any(|d| d == stem) && touched.insert(dependent.clone()) {
+                pending.push(dependent.clone());
+            }
+        }
+    }
+
+    component_files
+        .iter()
+        .filter(|file| touched.contains(*file))
+        .cloned()
+        .collect()
+}
+
+/// Parse `to_index` and put them in the index of `stored`, the components
+/// missing from `component_files` are dropped
+fn index_components(
+    mut stored: StoredIndex,
+    commit: &str,
+    component_files: &[String],
+    to_index: &[String],
+    link_base: Option<&str>,
+) -> StoredIndex {
+    let started = Instant::now();
+    let repo = stored.repo.clone();
+    let repo_root = format!("/etc/compo-doc/tmp/{repo}");
+
+    update_progress(|p| {
+        *p = SyncProgress {
+            running: true,
+            phase: "reading".to_string(),
+            total: to_index.len(),
+            started_at: Some(now_unix()),
+            ..Default::default()
+        }
+    });
+
+    // every source is read once, the examples are searched in memory
+    let files = read_repo_files(&repo_root, &CODE_FILE_EXTENSIONS);
+    let updated = last_commit_times(&repo_root, "components");
+
+    update_progress(|p| p.phase = "stories".to_string());
+    let csf_files = if to_index.len() < component_files.len() {
+        // a story imports its component, the other ones are not parsed
+        let stories: Vec<(String, String)> = files
+            .iter()
+            .filter(|(file, code)| {
+                to_index.iter().any(|component| {
+                    let stem = component_stem(component);
+                    code.contains(stem) || file.contains(stem)
+                })
+            })
+            .cloned()
+            .collect();
+        story_files(&stories)
+    } else {
+        story_files(&files)
+    };
+
+    update_progress(|p| p.phase = "components".to_string());
+    let sources: HashMap<&str, &str> = files
+        .iter()
+        .map(|(file, code)| (file.as_str(), code.as_str()))
+        .collect();
+    let mut indexed: HashMap<String, IndexedFile> = to_index
+        .par_iter()
+        .map(|file| {
+            let indexed = index_file(&repo, file, &sources, &files, &csf_files, link_base);
+            update_progress(|p| p.done += 1);
+            (file.clone(), indexed)
+        })
+        .collect();
+
+    update_progress(|p| p.phase = "committing".to_string());
+
+    // what is re-indexed or deleted goes, the rest is kept as it was
+    let kept =
+        |name: &String| component_files.contains(name) && !indexed.contains_key(name.as_str());
+    let dropped: Vec<String> = stored
+        .components
+        .iter()
+        .map(|c| c.name.clone())
+        .filter(|name| !kept(name))
+        .collect();
+    stored.props.retain(|name, _| kept(name));
+    stored.dependencies.retain(|name, _| kept(name));
+    stored.examples.retain(|name, _| kept(name));
+    stored
+        .documents
+        .retain(|document| kept(&document.component));
+    stored.diagnostics.retain(|diagnostic| {
+        !dropped
+            .iter()
+            .any(|name| diagnostic.subject == format!("components/{name}"))
+    });
+
+    let mut previous: HashMap<String, ComponentsList> = stored
+        .components
+        .drain(..)
+        .map(|entry| (entry.name.clone(), entry))
+        .collect();
+    let mut timings = Vec::new();
+
+    for name in component_files {
+        let Some(file) = indexed.remove(name) else {
+            if let Some(mut entry) = previous.remove(name) {
+                entry.updated_at = updated.get(name).copied();
+                stored.components.push(entry);
+            }
+            continue;
+        };
+
+        let mut entry = file.entry;
+        entry.updated_at = updated.get(name).copied();
+
+        // a repository component only, the npm ones are not merged
+        let dependencies = file
+            .dependencies
+            .into_iter()
+            .filter(|dependency| {
+                let dependency = format!("{dependency}.tsx");
+                component_files.contains(&dependency)
+            })
+            .collect();
+
+        stored.components.push(entry);
+        stored.documents.extend(file.document);
+        stored.props.insert(name.clone(), file.props);
+        stored.dependencies.insert(name.clone(), dependencies);
+        stored.examples.insert(name.clone(), file.examples);
+        stored.diagnostics.extend(file.diagnostic);
+        timings.push(file.timing);
+    }
+
+    stored.commit = commit.to_string();
+    stored.synced_at = now_unix();
+
+    timings.sort_by(|a, b| b.duration_ms.total_cmp(&a.duration_ms));
+    let duration_ms = started.elapsed().as_millis() as u64;
+    println!(
+        "indexed {} of {} components in {duration_ms}ms",
+        to_index.len(),
+        stored.components.len()
+    );
+
+    update_progress(|p| {
+        p.running = false;
+        p.phase = "done".to_string();
+        p.duration_ms = Some(duration_ms);
+        p.timings = timings;
+    });
+
+    stored
+}
+
+fn index_file(
+    repo: &str,
+    file: &str,
+    sources: &HashMap<&str, &str>,
+    files: &[(String, String)],
+    csf_files: &[(String, CsfFile)],
+    link_base: Option<&str>,
+) -> IndexedFile {
+    let started = Instant::now();
+    let path = format!("components/{file}");
+
+    let mut indexed = IndexedFile {
+        entry: index_component(file, ""),
+        document: None,
+        props: Vec::new(),
+        dependencies: Vec::new(),
+        examples: Vec::new(),
+        diagnostic: None,
+        timing: FileTiming {
+            file: path.clone(),
+            duration_ms: 0.0,
+        },
+    };
+
+    // the oversized files are not part of `files`, they are listed unparsed
+    let code = match sources.get(path.as_str()) {
+        Some(code) => Some(code.to_string()),
+        None => match read_repo_file(&format!("/etc/compo-doc/tmp/{repo}"), &path) {
+            Ok(code) => match check_file_size(&path, code.len()) {
+                Ok(()) => Some(code),
+                Err(diagnostic) => {
+                    indexed.diagnostic = Some(diagnostic);
+                    None
+                }
+            },
+            Err(_) => {
+                indexed.diagnostic = Some(LimitDiagnostic::unreadable_file(&path));
+                None
+            }
+        },
+    };
+
+    if let Some(code) = code {
+        // legacy annotation and metadata tags
+        indexed.entry = index_component(file, &code);
+        attach_storybook(std::slice::from_mut(&mut indexed.entry), csf_files);
+
+        let component_name = principal_component_name(&code)
+            .unwrap_or(file.split('.').next().unwrap_or(file).to_string());
+
+        indexed.props = extract_props(&code);
+        for (_, csf) in component_csf(csf_files, &component_name) {
+            enrich_props(&mut indexed.props, &csf.arg_types);
+        }
+
+        // merged when requested, the sync never pays for a crafted import graph
+        indexed.dependencies = get_imported_components(&code);
+
+        indexed.examples = component_examples(files, &path, &component_name, csf_files, link_base);
+        indexed.document = Some(SearchDocument::from_source(&indexed.entry, &code));
+    }
+
+    indexed.timing.duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    indexed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example(file: &str) -> UsageExample {
+        UsageExample {
+            kind: "call_site".to_string(),
+            story_id: None,
+            title: None,
+            link: None,
+            file: file.to_string(),
+            line: 1,
+            code: String::new(),
+            props: Vec::new(),
+            has_spread: false,
+        }
+    }
+
+    // Page merges Card which merges Button, Badge is shown in a docs page
+    fn previous() -> StoredIndex {
+        let names = [
+            "Button.tsx",
+            "Card.tsx",
+            "Page.tsx",
+            "Badge.tsx",
+            "Gone.tsx",
+        ];
+        StoredIndex {
+            version: STORED_INDEX_VERSION,
+            repo: "lib".to_string(),
+            commit: "c1".to_string(),
+            synced_at: 0,
+            components: names.iter().map(|n| index_component(n, "")).collect(),
+            props: HashMap::new(),
+            dependencies: HashMap::from([
+                ("Card.tsx".to_string(), vec!["Button".to_string()]),
+                ("Page.tsx".to_string(), vec!["Card".to_string()]),
+                ("Gone.tsx".to_string(), Vec::new()),
+            ]),
+            examples: HashMap::from([("Badge.tsx".to_string(), vec![example("docs/badges.tsx")])]),
+            documents: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn affected(repo_root: &str, changed: &[&str]) -> Vec<String> {
+        // Gone.tsx was deleted, New.tsx added
+        let component_files: Vec<String> =
+            ["Badge.tsx", "Button.tsx", "Card.tsx", "New.tsx", "Page.tsx"]
+                .iter()
+                .map(|f| f.to_string())
+                .collect();
+        let changed: Vec<String> = changed.iter().map(|f| f.to_string()).collect();
+        affected_components(&previous(), repo_root, &component_files, &changed)
+    }
+
+    #[test]
+    fn re_indexes_the_dependents_of_a_changed_component() {
+        assert_eq!(
+            affected("/nonexistent", &["components/Button.tsx"]),
+            ["Button.tsx", "Card.tsx", "New.tsx", "Page.tsx"]
+        );
+        assert_eq!(
+            affected("/nonexistent", &["components/Page.tsx"]),
+            ["New.tsx", "Page.tsx"]
+        );
+    }
+
+    #[test]
+    fn re_indexes_the_components_of_a_changed_example() {
+        // deleted, the previous examples still point at it
+        assert_eq!(
+            affected("/nonexistent", &["docs/badges.tsx"]),
+            ["Badge.tsx", "New.tsx"]
+        );
+
+        let root = std::env::temp_dir().join(format!("compo-doc-affected-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("pages")).unwrap();
+        std::fs::write(
+            root.join("pages/Home.tsx"),
+            "import { Card } from \"../components/Card\";\nexport const Home = () => <Card />;\n",
+        )
+        .unwrap();
+
+        // a new call site of Card, Page merges it
+        assert_eq!(
+            affected(root.to_str().unwrap(), &["pages/Home.tsx"]),
+            ["Card.tsx", "New.tsx", "Page.tsx"]
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{Result, anyhow};
+use swc_common::{FileName, SourceMap, Span, errors::SourceMapper, sync::Lrc};
+use swc_ecma_ast::{EsVersion, Expr, Lit, Module};
+use swc_ecma_parser::{Parser, StringInput, Syntax, TsSyntax, lexer::Lexer};
+
+use crate::utils::limits::check_file_size;
+
+/// A parsed tsx file keeping its source map around to resolve spans
+pub struct ParsedFile {
+    pub cm: Lrc<SourceMap>,
+    pub module: Module,
+}
+
+impl ParsedFile {
+    pub fn parse(code: &str) -> Result<Self> {
+        // big files are not worth the parser time, whatever they are
+        check_file_size("file", code.len()).map_err(|d| anyhow!(d.message()))?;
+
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Anon.into(), code.to_string());
+
+        let lexer = Lexer::new(
+            Syntax::Typescript(TsSyntax {
+                tsx: true,
+                ..Default::default()
+            }),
+            EsVersion::Es2022,
+            StringInput::from(&*fm),
+            None,
+        );
+
+        let module = Parser::new_from(lexer)
+            .parse_module()
+            .map_err(|e| anyhow!("could not parse file: {:?}", e.kind()))?;
+
+        Ok(ParsedFile { cm, module })
+    }
+
+    /// 1 based line of a span start
+    pub fn line_of(&self, span: Span) -> usize {
+        self.cm.lookup_char_pos(span.lo).line
+    }
+
+    pub fn snippet(&self, span: Span) -> String {
+        self.cm.span_to_snippet(span).unwrap_or_default()
+    }
+}
+
+/// Source-like text of a literal expression, `None` for anything dynamic
+pub fn literal_value(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Lit(Lit::Str(s)) => Some(format!("{:?}", s.value.to_string())),
+        Expr::Lit(Lit::Num(n)) => Some(n.value.to_string()),
+        Expr::Lit(Lit::Bool(b)) => Some(b.value.to_string()),
+        Expr::Lit(Lit::Null(_)) => Some("null".to_string()),
+        Expr::Tpl(tpl) if tpl.exprs.is_empty() => tpl
+            .quasis
+            .first()
+            .map(|q| format!("{:?}", q.raw.to_string())),
+        Expr::Unary(unary) => {
+            literal_value(&unary.arg).map(|value| format!("{}{value}", unary.op.as_str()))
+        }
+        Expr::Paren(paren) => literal_value(&paren.expr),
+        _ => None,
+    }
+}
@@ -1,8 +1,9 @@
 use std::collections::HashSet;
 
-use anyhow::{Result, bail};
-use swc_common::SourceMap;
+use anyhow::{Result, anyhow, bail};
+use swc_common::comments::{CommentKind, Comments, SingleThreadedComments};
 use swc_common::sync::Lrc;
+use swc_common::{BytePos, SourceMap};
 use swc_ecma_ast::*;
 use swc_ecma_codegen::{
     Emitter, Node,
@@ -11,9 +12,12 @@ use swc_ecma_codegen::{
 use swc_ecma_parser::{Parser, StringInput, Syntax, TsSyntax, lexer::Lexer};
 use swc_ecma_visit::Visit;
 
+use crate::utils::structs::PropInfo;
+
 pub struct TypeExtractor {
     pub target_type: String,
-    pub found_props: Option<Vec<(String, String)>>,
+    pub found_props: Option<Vec<PropInfo>>,
+    comments: SingleThreadedComments,
 }
 
 impl TypeExtractor {
@@ -21,11 +25,12 @@ impl TypeExtractor {
         Self {
             target_type: target_type.to_string(),
             found_props: None,
+            comments: SingleThreadedComments::default(),
         }
     }
 
     pub fn extract_from_str(&mut self, code: &str) -> Result<String> {
-        let cm = Lrc::new(SourceMap::default());
+        let comments = SingleThreadedComments::default();
         let lexer = Lexer::new(
             Syntax::Typescript(TsSyntax {
                 tsx: true,
@@ -33,22 +38,26 @@ impl TypeExtractor {
             }),
             EsVersion::Es2020,
             StringInput::new(code, Default::default(), Default::default()),
-            None,
+            Some(&comments),
         );
 
         let mut parser = Parser::new_from(lexer);
         let module = parser
             .parse_module()
-            .map_err(|e| println!("{:?}", e))
-            .unwrap();
+            .map_err(|e| anyhow!("could not parse component: {:?}", e.kind()))?;
 
+        // keep the comments around for the props descriptions
+        self.comments = comments;
         self.visit_module(&module);
 
         match &self.found_props {
             Some(props) => {
                 let formatted = props
                     .iter()
-                    .map(|(name, ty)| format!("  {}: {};", name, ty))
+                    .map(|p| {
+                        let optional = if p.optional { "?" } else { "" };
+                        format!("  {}{}: {};", p.name, optional, p.prop_type)
+                    })
                     .collect::<Vec<_>>()
                     .join("\n");
                 Ok(format!("{{\n{}\n}}", formatted))
@@ -56,82 +65,88 @@ impl TypeExtractor {
             None => bail!("Type/Interface '{}' not found", self.target_type),
         }
     }
+
+    fn collect_props(&self, members: &[TsTypeElement]) -> Vec<PropInfo> {
+        let mut props = Vec::new();
+        let cm = Lrc::new(SourceMap::default());
+
+        for member in members {
+            if let TsTypeElement::TsPropertySignature(prop) = member {
+                let prop_name = match &*prop.key {
+                    Expr::Ident(i) => i.sym.to_string(),
+                    Expr::Lit(Lit::Str(s)) => s.value.to_string(),
+                    _ => continue,
+                };
+
+                // First create the buffer
+                let mut type_buf = Vec::new();
+
+                // Use nested scope to contain the emitter
+                {
+                    let writer = JsWriter::new(cm.clone(), "\n", &mut type_buf, None);
+                    let mut emitter = Emitter {
+                        cfg: swc_ecma_codegen::Config::default(),
+                        cm: cm.clone(),
+                        comments: None,
+                        wr: Box::new(writer) as Box<dyn WriteJs>,
+                    };
+
+                    if let Some(type_ann) = &prop.type_ann {
+                        type_ann.type_ann.emit_with(&mut emitter).unwrap();
+                    } else {
+                        emitter.wr.write_str("any").unwrap();
+                    }
+                } // Emitter and writer are dropped here
+
+                // Now we can safely consume type_buf
+                let prop_type = String::from_utf8(type_buf).unwrap();
+                props.push(PropInfo {
+                    name: prop_name,
+                    prop_type: prop_type.trim().to_string(),
+                    optional: prop.optional,
+                    description: self.description_at(prop.span.lo),
+                    control: None,
+                    options: Vec::new(),
+                    default_value: None,
+                });
+            }
+        }
+
+        props
+    }
+
+    /// Text of the JSDoc block right above a position
+    fn description_at(&self, pos: BytePos) -> Option<String> {
+        let comments = self.comments.get_leading(pos)?;
+        let doc = comments
+            .iter()
+            .rev()
+            .find(|c| c.kind == CommentKind::Block && c.text.starts_with('*'))?;
+
+        let text = doc
+            .text
+            .lines()
+            .map(|l| l.trim().trim_start_matches('*').trim())
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if text.is_empty() { None } else { Some(text) }
+    }
 }
 
 impl Visit for TypeExtractor {
     fn visit_ts_type_alias_decl(&mut self, n: &TsTypeAliasDecl) {
-        if n.id.sym == self.target_type {
-            if let TsType::TsTypeLit(type_lit) = &*n.type_ann {
-                let mut props = Vec::new();
-                let cm = Lrc::new(SourceMap::default());
-
-                for member in &type_lit.members {
-                    if let TsTypeElement::TsPropertySignature(prop) = member {
-                        let prop_name = prop.key.as_ident().map(|i| i.sym.to_string()).unwrap();
-
-                        // First create the buffer
-                        let mut type_buf = Vec::new();
-
-                        // Use nested scope to contain the emitter
-                        {
-                            let writer = JsWriter::new(cm.clone(), "\n", &mut type_buf, None);
-                            let mut emitter = Emitter {
-                                cfg: swc_ecma_codegen::Config::default(),
-                                cm: cm.clone(),
-                                comments: None,
-                                wr: Box::new(writer) as Box<dyn WriteJs>,
-                            };
-
-                            if let Some(type_ann) = &prop.type_ann {
-                                type_ann.type_ann.emit_with(&mut emitter).unwrap();
-                            } else {
-                                emitter.wr.write_str("any").unwrap();
-                            }
-                        } // Emitter and writer are dropped here
-
-                        // Now we can safely consume type_buf
-                        let prop_type = String::from_utf8(type_buf).unwrap();
-                        props.push((prop_name, prop_type.trim().to_string()));
-                    }
-                }
-
-                self.found_props = Some(props);
-            }
+        if n.id.sym == self.target_type
+            && let TsType::TsTypeLit(type_lit) = &*n.type_ann
+        {
+            self.found_props = Some(self.collect_props(&type_lit.members));
         }
     }
 
     fn visit_ts_interface_decl(&mut self, n: &TsInterfaceDecl) {
         if n.id.sym == self.target_type {
-            let mut props = Vec::new();
-            let cm = Lrc::new(SourceMap::default());
-
-            for member in &n.body.body {
-                if let TsTypeElement::TsPropertySignature(prop) = member {
-                    let prop_name = prop.key.as_ident().map(|i| i.sym.to_string()).unwrap();
-
-                    let mut type_buf = Vec::new();
-                    {
-                        let writer = JsWriter::new(cm.clone(), "\n", &mut type_buf, None);
-                        let mut emitter = Emitter {
-                            cfg: swc_ecma_codegen::Config::default(),
-                            cm: cm.clone(),
-                            comments: None,
-                            wr: Box::new(writer) as Box<dyn WriteJs>,
-                        };
-
-                        if let Some(type_ann) = &prop.type_ann {
-                            type_ann.type_ann.emit_with(&mut emitter).unwrap();
-                        } else {
-                            emitter.wr.write_str("any").unwrap();
-                        }
-                    }
-
-                    let prop_type = String::from_utf8(type_buf).unwrap();
-                    props.push((prop_name, prop_type.trim().to_string()));
-                }
-            }
-
-            self.found_props = Some(props);
+            self.found_props = Some(self.collect_props(&n.body.body));
         }
     }
 }
@@ -158,8 +173,7 @@ pub fn find_used_type(code: &str) -> Result<Option<String>> {
     );
     let module = Parser::new_from(lexer)
         .parse_module()
-        .map_err(|e| println!("{:?}", e))
-        .unwrap();
+        .map_err(|e| anyhow!("could not parse component: {:?}", e.kind()))?;
 
     // 2. Walk the AST ----------------------------------------------------------
     let mut finder = Finder::default();
@@ -0,0 +1,116 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+};
+
+use crate::utils::{
+    examples::{CODE_FILE_EXTENSIONS, example_props, is_test_file},
+    jsx_usage::find_jsx_usages,
+    storybook::is_story_file,
+    structs::{ComponentUsage, ComponentUsages, PropUsageStat, PropValueCount},
+    utils::{read_repo_file, walk_repo_files},
+};
+
+/// Every JSX usage of a component across the repository, with how its props
+/// are used. `known_props` are the props declared by the component, the ones
+/// never passed anywhere are reported as unused.
+pub fn component_usages(
+    repo_root: &str,
+    component_path: &str,
+    component_name: &str,
+    known_props: &[String],
+) -> ComponentUsages {
+    let mut usages = Vec::new();
+
+    for file in walk_repo_files(repo_root, &CODE_FILE_EXTENSIONS) {
+        if file == component_path {
+            continue;
+        }
+
+        let Ok(code) = read_repo_file(repo_root, &file) else {
+            continue;
+        };
+
+        let kind = if is_story_file(&file) {
+            "story"
+        } else if is_test_file(&file) {
+            "test"
+        } else {
+            "call_site"
+        };
+
+        for usage in find_jsx_usages(&code, &file, component_path, component_name) {
+            usages.push(ComponentUsage {
+                kind: kind.to_string(),
+                file: file.clone(),
+                line: usage.line,
+                props: example_props(usage.props),
+                has_spread: usage.has_spread,
+            });
+        }
+    }
+
+    let prop_stats = prop_usage_stats(&usages);
+
+    let unused_props = known_props
+        .iter()
+        .filter(|p| !prop_stats.iter().any(|s| &s.name == *p))
+        .cloned()
+        .collect();
+
+    let mut files: Vec<&str> = usages.iter().map(|u| u.file.as_str()).collect();
+    files.sort();
+    files.dedup();
+
+    ComponentUsages {
+        component: component_name.to_string(),
+        total: usages.len(),
+        files: files.len(),
+        with_spread: usages.iter().filter(|u| u.has_spread).count(),
+        prop_stats,
+        unused_props,
+        usages,
+    }
+}
+
+/// How often each prop is passed and which literal values it gets, most used
+/// props first
+fn prop_usage_stats(usages: &[ComponentUsage]) -> Vec<PropUsageStat> {
+    // prop -> (count, value -> count, dynamic count)
+    let mut stats: HashMap<&str, (usize, BTreeMap<&str, usize>, usize)> = HashMap::new();
+
+    for usage in usages {
+        for prop in &usage.props {
+            let entry = stats.entry(prop.name.as_str()).or_default();
+            entry.0 += 1;
+            match &prop.value {
+                Some(value) => *entry.1.entry(value.as_str()).or_default() += 1,
+                None => entry.2 += 1,
+            }
+        }
+    }
+
+    let mut stats: Vec<PropUsageStat> = stats
+        .into_iter()
+        .map(|(name, (count, values, dynamic))| {
+            let mut values: Vec<PropValueCount> = values
+                .into_iter()
+                .map(|(value, count)| PropValueCount {
+                    value: value.to_string(),
+                    count,
+                })
+                .collect();
+            values.sort_by_key(|v| Reverse(v.count));
+
+            PropUsageStat {
+                name: name.to_string(),
+                count,
+                dynamic,
+                values,
+            }
+        })
+        .collect();
+
+    stats.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    stats
+}
@@ -1,26 +1,110 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs::{OpenOptions, create_dir_all, read_dir, read_to_string},
+    fs::{OpenOptions, canonicalize, create_dir_all, read_dir, read_to_string},
     io::{Read, Write},
-    path::Path,
-    process::Command,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
+use rayon::prelude::*;
 use regex::Regex;
 
+use crate::utils::{
+    limits::{check_repo_size, limits},
+    structs::{ComponentsList, RepoConfig},
+};
+
 pub fn get_git_infos() -> () {}
 
-pub fn execute_commande(commande: &str) -> Result<String, String> {
-    match Command::new("sh").arg("-c").arg(commande).output() {
-        Ok(output) => {
-            if !output.status.success() {
-                // let y = String::
-                return Err(String::from_utf8_lossy(&output.stderr).to_string());
+/// Run git in `repo_dir` without going through a shell, the arguments are
+/// passed as is so user given refs can not inject commands. git is killed past
+/// the configured timeout.
+pub fn git_output(repo_dir: &str, args: &[&str]) -> Result<String, String> {
+    run_git(repo_dir, args, None)
+}
+
+/// `git_output` for the commands downloading the repository into
+/// `watched_dir`, git is killed as soon as the folder passes the repository
+/// size limit instead of once everything landed on disk
+pub fn git_output_watched(
+    repo_dir: &str,
+    args: &[&str],
+    watched_dir: &str,
+) -> Result<String, String> {
+    run_git(repo_dir, args, Some(watched_dir))
+}
+
+// how often the size of a watched download is measured
+const SIZE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+fn run_git(repo_dir: &str, args: &[&str], watched_dir: Option<&str>) -> Result<String, String> {
+    let timeout = Duration::from_secs(limits().git_timeout_secs);
+
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(repo_dir)
+        .args(args)
+        // fail instead of waiting for credentials on a private repository
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| err.to_string())?;
+
+    // drained on their own threads so a full pipe never blocks git
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let started = Instant::now();
+    let mut size_checked = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() > timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "git {} timed out after {}s (git_timeout_secs)",
+                    args.first().unwrap_or(&""),
+                    timeout.as_secs()
+                ));
             }
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            Ok(None) => {
+                if let Some(dir) = watched_dir
+                    && size_checked.elapsed() > SIZE_CHECK_INTERVAL
+                {
+                    size_checked = Instant::now();
+                    if let Err(diagnostic) = check_repo_size(dir) {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        return Err(diagnostic.message());
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            Err(err) => return Err(err.to_string()),
         }
-        Err(err) => Err(err.to_string()),
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        return Err(String::from_utf8_lossy(&stderr).to_string());
     }
+    Ok(String::from_utf8_lossy(&stdout).to_string())
+}
+
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut out = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut out);
+        }
+        out
+    })
 }
 
 pub fn check_dir_exist_or_create(file_path: &str) -> () {
@@ -61,6 +145,34 @@ pub fn read_from_file_ut(file_path: &str) -> Result<String, String> {
     };
 }
 
+/// `rel_path` inside the clone with its symlinks resolved, the clones are
+/// untrusted so anything resolving outside of `repo_root` is refused
+pub fn confined_repo_path(repo_root: &str, rel_path: &str) -> Result<PathBuf, String> {
+    let root = canonicalize(repo_root).map_err(|err| format!("{repo_root} : {err}"))?;
+    let path = canonicalize(root.join(rel_path)).map_err(|err| format!("{rel_path} : {err}"))?;
+
+    if !path.starts_with(&root) {
+        return Err(format!("{rel_path} resolves outside of the repository"));
+    }
+    Ok(path)
+}
+
+/// Read a file of the clone, every read of repository content goes through here
+pub fn read_repo_file(repo_root: &str, rel_path: &str) -> Result<String, String> {
+    read_to_string(confined_repo_path(repo_root, rel_path)?).map_err(|err| err.to_string())
+}
+
+pub fn read_repo_bytes(repo_root: &str, rel_path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(confined_repo_path(repo_root, rel_path)?).map_err(|err| err.to_string())
+}
+
+/// Size in bytes of a file of the clone, checked before reading it whole
+pub fn repo_file_size(repo_root: &str, rel_path: &str) -> Result<u64, String> {
+    std::fs::metadata(confined_repo_path(repo_root, rel_path)?)
+        .map(|metadata| metadata.len())
+        .map_err(|err| err.to_string())
+}
+
 pub fn list_dir_contents(path: &str) -> Result<Vec<String>, bool> {
     let dir_content = match read_dir(path) {
         Ok(content) => content,
@@ -103,8 +215,9 @@ pub fn list_dir_contents(path: &str) -> Result<Vec<String>, bool> {
     return Ok(content);
 }
 
-pub fn extract_repo_info(url: &str) -> Option<(&str, &str, &str)> {
-    let parts: Vec<&str> = url.split('/').collect();
+/// Owner, name and branch of the configured repository
+pub fn extract_repo_info(config: &RepoConfig) -> Option<(&str, &str, &str)> {
+    let parts: Vec<&str> = config.url.split('/').collect();
 
     // We need at least username and repo parts
     if parts.len() < 2 {
@@ -112,44 +225,332 @@ pub fn extract_repo_info(url: &str) -> Option<(&str, &str, &str)> {
     }
 
     // Get the last part (repo) and strip .git suffix
-    let repo = parts.get(parts.len() - 2)?.strip_suffix(".git")?;
+    let repo = parts.last()?.strip_suffix(".git")?;
 
     // The username should be second-to-last for standard GitHub URLs
     // Handle cases like "https://github.com/owner/repo.git"
-    let username = parts.get(parts.len() - 3)?;
+    let username = parts.get(parts.len() - 2)?;
 
-    let branche = parts.last()?;
+    Some((username, repo, &config.branch))
+}
+
+/// The repository config, the url and the branch apart. A config saved as
+/// `<url>/<branch>` by a previous version is still read, its branch had no `/`.
+pub fn parse_repo_config(content: &str) -> Option<RepoConfig> {
+    if let Ok(config) = serde_json::from_str::<RepoConfig>(content) {
+        return Some(config);
+    }
 
-    Some((username, repo, branche))
+    let (url, branch) = content.trim().rsplit_once('/')?;
+    Some(RepoConfig {
+        url: url.to_string(),
+        branch: branch.to_string(),
+    })
 }
 
-pub fn get_new_repo_ver(repo: &str, branch: &str, username: &str) -> Result<bool, String> {
-    let git_repo = format!("https://github.com/{username}/{repo}.git");
+pub fn read_repo_config() -> Result<RepoConfig, String> {
+    let content = read_from_file_ut("/etc/compo-doc/config/config")?;
+    parse_repo_config(&content).ok_or("invalid repository config".to_string())
+}
+
+/// Move the clone checked out at `from_commit` to the head of the branch and
+/// list the files changed in between. Fails when the clone is missing or not
+/// at `from_commit`, the caller then clones again.
+pub fn update_repo_ver(
+    repo_url: &str,
+    repo: &str,
+    branch: &str,
+    from_commit: &str,
+) -> Result<Vec<String>, String> {
+    let repo_dir = format!("/etc/compo-doc/tmp/{repo}");
+
+    if !Path::new(&repo_dir).exists() {
+        return Err(format!("no clone of {repo}"));
+    }
+    if git_output(&repo_dir, &["rev-parse", "HEAD"])?.trim() != from_commit {
+        return Err(format!("the clone of {repo} is not at {from_commit}"));
+    }
+    // the repository of the config changed, the name only is the same
+    if git_output(&repo_dir, &["remote", "get-url", "origin"])?.trim() != repo_url {
+        return Err(format!("the clone of {repo} comes from another url"));
+    }
+
+    git_output_watched(&repo_dir, &["fetch", "origin", branch], &repo_dir)?;
+    git_output(&repo_dir, &["reset", "--hard", "FETCH_HEAD"])?;
+    git_output(&repo_dir, &["clean", "-fdx"])?;
+
+    if let Err(diagnostic) = check_repo_size(&repo_dir) {
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        return Err(diagnostic.message());
+    }
+
+    // a rename is a deletion and an addition
+    let diff = git_output(
+        &repo_dir,
+        &["diff", "--name-only", "--no-renames", from_commit, "HEAD"],
+    )?;
+    Ok(diff.lines().map(str::to_string).collect())
+}
+
+/// Clone the branch of the repository again, git is run without a shell
+pub fn get_new_repo_ver(repo_url: &str, repo: &str, branch: &str) -> Result<bool, String> {
+    let repo_dir = format!("/etc/compo-doc/tmp/{repo}");
+
+    if Path::new(&repo_dir).exists() {
+        std::fs::remove_dir_all(&repo_dir).map_err(|err| err.to_string())?;
+    }
 
-    match execute_commande(&format!("rm -rf /etc/compo-doc/tmp/{}", &repo)) {
-        Ok(r) => r,
+    // Clone the repo inside the machine
+    let cloned = git_output_watched(
+        "/etc/compo-doc/tmp",
+        &[
+            "clone",
+            "-b",
+            branch,
+            "--single-branch",
+            "--",
+            repo_url,
+            repo,
+        ],
+        &repo_dir,
+    );
+    if let Err(err) = cloned {
+        // what an interrupted clone left behind
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        return Err(err);
+    }
+
+    // an oversized repository is not kept around
+    if let Err(diagnostic) = check_repo_size(&repo_dir) {
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        return Err(diagnostic.message());
+    }
+
+    Ok(true)
+}
+
+pub fn convert_hash<'a>(input: &'a HashMap<String, String>) -> HashMap<&'a str, &'a str> {
+    input
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect()
+}
+
+/// Path of a component file of the synced index, `None` for an unknown id or a
+/// path escaping the repository working tree
+pub fn component_file_path(liste: &[ComponentsList], repo: &str, id: &str) -> Option<String> {
+    // only the components of the index can be served
+    if id.contains(['/', '\\', '\0']) || id.contains("..") || !liste.iter().any(|c| c.name == id) {
+        return None;
+    }
+
+    let file = confined_repo_path(
+        &format!("/etc/compo-doc/tmp/{repo}"),
+        &format!("components/{id}"),
+    )
+    .ok()?;
+    if !file.is_file() {
+        return None;
+    }
+
+    Some(file.to_string_lossy().to_string())
+}
+
+/// Resolve `.` and `..` segments without touching the file system
+pub fn normalize_path(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => continue,
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+/// Unix time of the last commit touching each file under `path`
+/// (file names relative to `path`)
+pub fn last_commit_times(repo_dir: &str, path: &str) -> HashMap<String, i64> {
+    let mut times = HashMap::new();
+
+    let log = match git_output(
+        repo_dir,
+        &["log", "--format=%x00%ct", "--name-only", "--", path],
+    ) {
+        Ok(log) => log,
         Err(err) => {
-            return Err(err);
+            println!("could not read the git history : {err}");
+            return times;
         }
     };
 
-    // Execute commande to clone repo inside machine
-    match execute_commande(&format!(
-        "cd /etc/compo-doc/tmp && git clone -b {} --single-branch {}",
-        branch, git_repo
-    )) {
-        Ok(_r) => {
-            return Ok(true);
+    let prefix = format!("{}/", path.trim_end_matches('/'));
+    let mut current: Option<i64> = None;
+
+    // the log is newest first, the first time we meet a file is its last change
+    for line in log.lines() {
+        if let Some(stamp) = line.strip_prefix('\0') {
+            current = stamp.trim().parse().ok();
+            continue;
         }
-        Err(err) => {
-            // REturn error ro user
-            return Err(err);
+        let (Some(time), Some(file)) = (current, line.strip_prefix(&prefix)) else {
+            continue;
+        };
+        times.entry(file.to_string()).or_insert(time);
+    }
+
+    times
+}
+
+// folders that never contain the repository own sources
+const IGNORED_DIRS: [&str; 8] = [
+    "node_modules",
+    ".git",
+    "dist",
+    "build",
+    "coverage",
+    ".next",
+    "storybook-static",
+    "out",
+];
+
+/// Every file of the repository with one of the extensions, paths relative
+/// to `root`, sorted
+pub fn walk_repo_files(root: &str, extensions: &[&str]) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut dirs = vec![String::new()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = read_dir(format!("{root}/{dir}")) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let rel = if dir.is_empty() {
+                name.clone()
+            } else {
+                format!("{dir}/{name}")
+            };
+
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                if !IGNORED_DIRS.contains(&name.as_str()) {
+                    dirs.push(rel);
+                }
+            } else if file_type.is_file()
+                && extensions
+                    .iter()
+                    .any(|ext| name.ends_with(&format!(".{ext}")))
+                && !name.ends_with(".d.ts")
+            {
+                files.push(rel);
+            }
         }
     }
+
+    files.sort();
+    files
 }
-pub fn convert_hash<'a>(input: &'a HashMap<String, String>) -> HashMap<&'a str, &'a str> {
-    input
-        .iter()
-        .map(|(k, v)| (k.as_str(), v.as_str()))
+
+/// Read the files of `walk_repo_files` in parallel, the ones over the file size
+/// limit are left out
+pub fn read_repo_files(root: &str, extensions: &[&str]) -> Vec<(String, String)> {
+    walk_repo_files(root, extensions)
+        .into_par_iter()
+        .filter_map(|file| {
+            let code = read_repo_file(root, &file).ok()?;
+            (code.len() as u64 <= limits().max_file_size).then_some((file, code))
+        })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a clone holding one file, and a secret next to it
+    fn fake_clone(name: &str) -> (PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("compo-doc-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_dir_all(dir.join("repo/components")).unwrap();
+        std::fs::write(dir.join("repo/components/Button.tsx"), "button").unwrap();
+        std::fs::write(dir.join("secret"), "secret").unwrap();
+        let root = dir.join("repo").to_string_lossy().to_string();
+        (dir, root)
+    }
+
+    #[test]
+    fn reads_the_files_of_the_clone() {
+        let (dir, root) = fake_clone("read");
+        assert_eq!(
+            read_repo_file(&root, "components/Button.tsx").unwrap(),
+            "button"
+        );
+        assert_eq!(
+            read_repo_file(&root, "components/../components/Button.tsx").unwrap(),
+            "button"
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn refuses_paths_leaving_the_clone() {
+        let (dir, root) = fake_clone("escape");
+        assert!(read_repo_file(&root, "../secret").is_err());
+        assert!(read_repo_file(&root, "components/../../secret").is_err());
+        let absolute = dir.join("secret").to_string_lossy().to_string();
+        assert!(read_repo_file(&root, &absolute).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn refuses_symlinks_leaving_the_clone() {
+        let (dir, root) = fake_clone("symlink");
+        std::os::unix::fs::symlink(dir.join("secret"), dir.join("repo/components/Link.tsx"))
+            .unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("repo/parent")).unwrap();
+
+        assert!(read_repo_file(&root, "components/Link.tsx").is_err());
+        assert!(read_repo_file(&root, "parent/secret").is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn normalizes_relative_segments() {
+        assert_eq!(
+            normalize_path("components/./a/../b.css"),
+            "components/b.css"
+        );
+        assert_eq!(normalize_path("../x"), "x");
+    }
+
+    #[test]
+    fn keeps_the_slashes_of_the_branch_apart_from_the_url() {
+        let config = parse_repo_config(
+            r#"{"url": "https://github.com/owner/lib.git", "branch": "feature/login"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            extract_repo_info(&config),
+            Some(("owner", "lib", "feature/login"))
+        );
+    }
+
+    #[test]
+    fn reads_the_config_of_the_previous_format() {
+        let config = parse_repo_config("https://github.com/owner/lib.git/main\n").unwrap();
+        assert_eq!(config.url, "https://github.com/owner/lib.git");
+        assert_eq!(config.branch, "main");
+        assert_eq!(extract_repo_info(&config), Some(("owner", "lib", "main")));
+        assert!(parse_repo_config("main").is_none());
+    }
+}